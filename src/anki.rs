@@ -0,0 +1,772 @@
+use reqwest::Response;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::markdown::field_html;
+use crate::parser::Flashcard;
+use crate::state::NOTE_KEY_TAG_PREFIX;
+
+/// Resolve the Anki deck for a page. With a configured deck name and per-page
+/// decks enabled, the page becomes a subdeck (`Parent::Page Title`).
+pub fn deck_name_for_page(page_title: &str, config: &Config) -> String {
+    match (&config.deck_name, config.deck_per_page) {
+        (Some(parent), true) => format!("{}{}{}", parent, config.deck_separator, page_title),
+        (Some(deck_name), false) => deck_name.clone(),
+        (None, _) => page_title.to_string(),
+    }
+}
+
+/// Send an Anki-Connect request, adding the API key when one is configured.
+/// A refused connection, e.g. while Anki is syncing, is retried up to
+/// `ANKI_CONNECT_RETRIES` times; such a request never reached Anki, so it
+/// can't be carried out twice.
+pub(crate) async fn anki_post(data: &Value, config: &Config) -> Result<Response> {
+    let mut request = data.clone();
+    if let Some(api_key) = &config.anki_connect_api_key {
+        request["key"] = json!(api_key);
+    }
+    let mut attempt = 0;
+    loop {
+        match config.http_client().post(&config.anki_connect_url).json(&request).send().await {
+            Err(error) if error.is_connect() && attempt < config.anki_connect_retries => {
+                let delay = Duration::from_millis(500 << attempt.min(6));
+                attempt += 1;
+                warn!(
+                    "Anki-Connect refused the connection, retrying in {:.1}s (attempt {}/{})",
+                    delay.as_secs_f64(), attempt, config.anki_connect_retries
+                );
+                tokio::time::sleep(delay).await;
+            },
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// Oldest Anki-Connect API version whose actions are used here
+const MIN_ANKI_CONNECT_VERSION: u64 = 6;
+
+/// Make sure Anki-Connect answers and is recent enough, before any work is
+/// done, and return its API version
+pub async fn check_anki_connect(config: &Config) -> Result<u64> {
+    let anki_connect_url = &config.anki_connect_url;
+    
+    let version_data = json!({
+        "action": "version",
+        "version": MIN_ANKI_CONNECT_VERSION
+    });
+    
+    let unreachable = || Error::AnkiConnect(format!(
+        "Cannot reach Anki-Connect at {}; is Anki open with the add-on installed?",
+        anki_connect_url
+    ));
+    let response = anki_post(&version_data, config)
+        .await
+        .map_err(|_| unreachable())?;
+    
+    let response_text = response.text().await.map_err(|_| unreachable())?;
+    
+    debug!("Anki-Connect version response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text).map_err(|_| unreachable())?;
+    // Anki-Connect answers with a bare number when called without a version
+    let version = response_json["result"].as_u64()
+        .or_else(|| response_json.as_u64())
+        .ok_or_else(unreachable)?;
+    if version < MIN_ANKI_CONNECT_VERSION {
+        return Err(Error::AnkiConnect(format!(
+            "Anki-Connect at {} is version {}, but version {} or newer is required; please update the add-on",
+            anki_connect_url, version, MIN_ANKI_CONNECT_VERSION
+        )));
+    }
+    Ok(version)
+}
+
+pub async fn create_deck_if_not_exists(deck_name: &str, config: &Config) -> Result<()> {
+    let create_deck_data = json!({
+        "action": "createDeck",
+        "version": 6,
+        "params": {
+            "deck": deck_name
+        }
+    });
+    
+    debug!("Creating deck: {}", serde_json::to_string_pretty(&create_deck_data).unwrap());
+    
+    let response = anki_post(&create_deck_data, config).await?;
+    
+    let response_text = response.text().await?;
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    
+    debug!("Create deck response: {}", serde_json::to_string_pretty(&response_json)?);
+    
+    // Without the deck every addNote would fail, so stop here instead
+    if response_json["error"].is_null() {
+        Ok(())
+    } else {
+        Err(Error::AnkiConnect(format!("failed to create deck \"{}\": {}", deck_name, response_json["error"])))
+    }
+}
+
+/// Anki search for the notes `clear_deck` deletes. `deck:` also matches the
+/// subdecks, which hold the cards of other pages with `DECK_HIERARCHY`. The
+/// name is escaped, or `*` and `_` in it would match other decks too.
+pub fn clear_deck_query(deck_name: &str) -> String {
+    format!("\"deck:{0}\" -\"deck:{0}::*\"", escape_anki_search(deck_name))
+}
+
+/// Delete every note in the deck, for `CLEAR_DECK`
+pub async fn clear_deck(deck_name: &str, config: &Config) -> Result<()> {
+    // Get all notes in the deck (deleteNotes takes note ids, not card ids)
+    let find_notes_data = json!({
+        "action": "findNotes",
+        "version": 6,
+        "params": {
+            "query": clear_deck_query(deck_name)
+        }
+    });
+    
+    debug!("Finding notes in deck: {}", serde_json::to_string_pretty(&find_notes_data).unwrap());
+    
+    let response = anki_post(&find_notes_data, config).await?;
+    
+    let response_text = response.text().await?;
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    
+    if let Some(note_ids) = response_json["result"].as_array() {
+        if !note_ids.is_empty() {
+            info!("Clearing {} notes from deck \"{}\"", note_ids.len(), deck_name);
+            
+            // Delete all notes
+            let delete_notes_data = json!({
+                "action": "deleteNotes",
+                "version": 6,
+                "params": {
+                    "notes": note_ids
+                }
+            });
+            
+            debug!("Deleting notes: {}", serde_json::to_string_pretty(&delete_notes_data).unwrap());
+            
+            let delete_response = anki_post(&delete_notes_data, config).await?;
+            
+            let delete_response_text = delete_response.text().await?;
+            
+            debug!("Delete notes response: {}", delete_response_text);
+        } else {
+            debug!("Deck \"{}\" is empty, no need to clear", deck_name);
+        }
+    }
+    
+    Ok(())
+}
+
+pub async fn delete_notes(note_ids: &[u64], config: &Config) -> Result<()> {
+    let delete_notes_data = json!({
+        "action": "deleteNotes",
+        "version": 6,
+        "params": {
+            "notes": note_ids
+        }
+    });
+    
+    debug!("Deleting notes: {}", serde_json::to_string_pretty(&delete_notes_data).unwrap());
+    
+    let response = anki_post(&delete_notes_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Delete notes response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if response_json["error"].is_null() {
+        Ok(())
+    } else {
+        Err(Error::AnkiConnect(response_json["error"].to_string()))
+    }
+}
+
+/// Names the stock note types have in the languages Anki is commonly used in
+const BASIC_MODEL_NAMES: [&str; 3] = ["Basic", "基本", "Basique"];
+const CLOZE_MODEL_NAMES: [&str; 3] = ["Cloze", "填空题", "Texte à trous"];
+
+/// Note type `--create-model` creates when Anki has none of the basic ones
+const CREATED_MODEL_NAME: &str = "notion2anki Basic";
+
+/// Note types notes are added with, picked once per run by `resolve_models`
+#[derive(Debug, Clone)]
+pub struct AnkiModels {
+    pub basic: String,
+    pub cloze: String,
+    /// The basic note type is the one a dry run with `--create-model` would create
+    pub basic_pending: bool,
+}
+
+/// Names of all decks in the Anki collection
+pub async fn deck_names(config: &Config) -> Result<Vec<String>> {
+    let deck_names_data = json!({
+        "action": "deckNames",
+        "version": 6
+    });
+    
+    let response = anki_post(&deck_names_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Deck names response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    Ok(response_json["result"]
+        .as_array()
+        .map(|names| names.iter().filter_map(|name| name.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default())
+}
+
+/// Id of the `DECK_OPTIONS` group, looked up once per run. Anki-Connect can
+/// only tell the group of a deck, so the group is found through the decks
+/// using it, and created as a copy of the Default group when none does.
+async fn deck_options_id(group_name: &str, config: &Config) -> Result<u64> {
+    if let Some(id) = config.deck_options_group.get() {
+        return Ok(*id);
+    }
+    let mut id = None;
+    for deck_name in deck_names(config).await? {
+        let deck_config = anki_action("getDeckConfig", json!({ "deck": deck_name }), config).await?;
+        if deck_config["name"] == group_name {
+            id = deck_config["id"].as_u64();
+            break;
+        }
+    }
+    let id = match id {
+        Some(id) => id,
+        None => {
+            let created = anki_action("cloneDeckConfigId", json!({ "name": group_name, "cloneFrom": 1 }), config).await?;
+            info!("Created deck options group \"{}\"", group_name);
+            created.as_u64().ok_or_else(|| Error::AnkiConnect(format!("failed to create deck options group \"{}\"", group_name)))?
+        },
+    };
+    config.deck_options_group.set(id).ok();
+    Ok(id)
+}
+
+/// Put a deck into the `DECK_OPTIONS` group
+pub async fn set_deck_options(deck_name: &str, group_name: &str, config: &Config) -> Result<()> {
+    let id = deck_options_id(group_name, config).await?;
+    let updated = anki_action("setDeckConfigId", json!({ "decks": [deck_name], "configId": id }), config).await?;
+    if updated != true {
+        return Err(Error::AnkiConnect(format!("failed to set the options of deck \"{}\"", deck_name)));
+    }
+    debug!("Set deck \"{}\" to options group \"{}\"", deck_name, group_name);
+    Ok(())
+}
+
+/// Send an Anki-Connect action and return its result
+async fn anki_action(action: &str, params: Value, config: &Config) -> Result<Value> {
+    let data = json!({
+        "action": action,
+        "version": 6,
+        "params": params
+    });
+    
+    let response = anki_post(&data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("{} response: {}", action, response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    Ok(response_json["result"].clone())
+}
+
+/// Names of all note types in the Anki collection
+async fn model_names(config: &Config) -> Result<Vec<String>> {
+    let model_names_data = json!({
+        "action": "modelNames",
+        "version": 6
+    });
+    
+    let response = anki_post(&model_names_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Model names response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    Ok(response_json["result"]
+        .as_array()
+        .map(|names| names.iter().filter_map(|name| name.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default())
+}
+
+/// The configured note type if Anki has it, otherwise the first of its
+/// localized names that Anki has. Custom names have no localized names.
+fn pick_model(configured: &str, localized: &[&str], available: &[String]) -> Option<String> {
+    let mut candidates = vec![configured];
+    if localized.contains(&configured) {
+        candidates.extend(localized.iter().copied().filter(|name| *name != configured));
+    }
+    candidates
+        .into_iter()
+        .find(|name| available.iter().any(|model| model == name))
+        .map(|name| name.to_string())
+}
+
+/// The note types `resolve_models` would pick for basic and cloze notes,
+/// `None` when Anki has none of their names. Nothing is created.
+pub async fn find_models(config: &Config) -> Result<(Option<String>, Option<String>)> {
+    let available = model_names(config).await?;
+    let basic = pick_model(&config.model_name, &BASIC_MODEL_NAMES, &available)
+        .or_else(|| available.iter().find(|model| *model == CREATED_MODEL_NAME).cloned());
+    let cloze = pick_model(&config.cloze_model_name, &CLOZE_MODEL_NAMES, &available);
+    Ok((basic, cloze))
+}
+
+/// Look up which note types Anki has and pick the ones for basic and cloze
+/// notes, once per run. A missing basic note type is an error; a missing
+/// cloze note type only fails the cloze cards, so it is a warning.
+pub async fn resolve_models(config: &Config) -> Result<()> {
+    if config.anki_models.get().is_some() {
+        return Ok(());
+    }
+    let available = model_names(config).await?;
+    
+    let configured = &config.model_name;
+    let mut basic_pending = false;
+    let basic = match pick_model(configured, &BASIC_MODEL_NAMES, &available) {
+        Some(basic) => basic,
+        // An earlier run may have created it already
+        None if available.iter().any(|model| model == CREATED_MODEL_NAME) => CREATED_MODEL_NAME.to_string(),
+        None if config.create_model && config.dry_run => {
+            println!("Would create the note type \"{}\"", CREATED_MODEL_NAME);
+            basic_pending = true;
+            CREATED_MODEL_NAME.to_string()
+        },
+        None if config.create_model => {
+            create_basic_model(CREATED_MODEL_NAME, config).await?;
+            CREATED_MODEL_NAME.to_string()
+        },
+        None => return Err(Error::Config(format!(
+            "Anki has no note type \"{}\"; set ANKI_MODEL_NAME to one of: {}, or pass --create-model to create one",
+            configured,
+            available.join(", ")
+        ))),
+    };
+    
+    let configured_cloze = &config.cloze_model_name;
+    let cloze = pick_model(configured_cloze, &CLOZE_MODEL_NAMES, &available).unwrap_or_else(|| {
+        warn!("Anki has no note type \"{}\", so cloze cards can't be added; set ANKI_CLOZE_MODEL_NAME to one of its note types", configured_cloze);
+        configured_cloze.clone()
+    });
+    
+    debug!("Using note types \"{}\" and \"{}\"", basic, cloze);
+    config.anki_models.set(AnkiModels { basic, cloze, basic_pending }).ok();
+    Ok(())
+}
+
+/// Add a note type with the Front and Back fields and a single card showing
+/// the Back below the Front
+async fn create_basic_model(model_name: &str, config: &Config) -> Result<()> {
+    let create_model_data = json!({
+        "action": "createModel",
+        "version": 6,
+        "params": {
+            "modelName": model_name,
+            "inOrderFields": [&config.front_field, &config.back_field],
+            "isCloze": false,
+            "cardTemplates": [{
+                "Name": "Card 1",
+                "Front": format!("{{{{{}}}}}", config.front_field),
+                "Back": format!("{{{{FrontSide}}}}<hr id=answer>{{{{{}}}}}", config.back_field)
+            }]
+        }
+    });
+    
+    debug!("Creating note type: {}", serde_json::to_string_pretty(&create_model_data).unwrap());
+    
+    let response = anki_post(&create_model_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Create note type response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    info!("Created note type \"{}\"", model_name);
+    Ok(())
+}
+
+/// Model used for basic notes
+fn anki_model_name(config: &Config) -> String {
+    config.anki_models.get().map(|models| models.basic.clone()).unwrap_or_else(|| config.model_name.clone())
+}
+
+/// Model used for cloze notes
+fn anki_cloze_model_name(config: &Config) -> String {
+    config.anki_models.get().map(|models| models.cloze.clone()).unwrap_or_else(|| config.cloze_model_name.clone())
+}
+
+/// Field names of the note type used for basic cards
+pub async fn model_field_names(config: &Config) -> Result<Vec<String>> {
+    if config.anki_models.get().is_some_and(|models| models.basic_pending) {
+        return Ok(vec![config.front_field.clone(), config.back_field.clone()]);
+    }
+    let model_fields_data = json!({
+        "action": "modelFieldNames",
+        "version": 6,
+        "params": {
+            "modelName": anki_model_name(config)
+        }
+    });
+    
+    debug!("Getting model fields: {}", serde_json::to_string_pretty(&model_fields_data).unwrap());
+    
+    let response = anki_post(&model_fields_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Model fields response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    Ok(response_json["result"]
+        .as_array()
+        .map(|names| names.iter().filter_map(|name| name.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default())
+}
+
+/// Fail early when the note type lacks the fields basic cards are written to.
+/// Returns all the fields of the note type.
+pub async fn check_model_fields(config: &Config) -> Result<Vec<String>> {
+    let model_fields = model_field_names(config).await?;
+    for field in [&config.front_field, &config.back_field] {
+        if !model_fields.contains(field) {
+            return Err(Error::Config(format!(
+                "The note type \"{}\" has no field \"{}\"; set ANKI_FRONT_FIELD and ANKI_BACK_FIELD to two of its fields: {}",
+                anki_model_name(config),
+                field,
+                model_fields.join(", ")
+            )));
+        }
+    }
+    Ok(model_fields)
+}
+
+/// Anki tags for notes from a page: the import tag and the slugified page title
+pub fn page_tags(page_title: &str, config: &Config) -> Vec<String> {
+    let mut tags = Vec::new();
+    if !config.import_tag.is_empty() {
+        tags.push(config.import_tag.clone());
+    }
+    if config.tag_page_title {
+        let slug = slugify_tag(page_title);
+        if !slug.is_empty() {
+            tags.push(slug);
+        }
+    }
+    tags
+}
+
+/// Anki tags can't contain spaces, so anything but letters and digits
+/// becomes a single dash
+fn slugify_tag(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Anki-Connect note object shared by addNotes and canAddNotes.
+/// Cards with cloze deletions use the cloze model, whose single Text field
+/// holds the question and answer on separate lines. Cards with mapped
+/// fields fill exactly those fields. The card's own tags follow `tags`.
+pub fn build_note(flashcard: &Flashcard, deck_name: &str, tags: &[String], config: &Config) -> Value {
+    let model_name = if flashcard.is_cloze() && flashcard.fields.is_empty() {
+        anki_cloze_model_name(config)
+    } else {
+        anki_model_name(config)
+    };
+    json!({
+        "deckName": deck_name,
+        "modelName": model_name,
+        "fields": note_fields(flashcard, config),
+        "tags": tags.iter().chain(&flashcard.tags).collect::<Vec<_>>()
+    })
+}
+
+/// Text of a cloze note: the question and answer on separate lines
+pub(crate) fn cloze_text(flashcard: &Flashcard) -> String {
+    [flashcard.question.as_str(), flashcard.answer.as_str()]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| field_html(part))
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// Field values of the note for a card
+fn note_fields(flashcard: &Flashcard, config: &Config) -> Value {
+    if !flashcard.fields.is_empty() {
+        let fields: serde_json::Map<String, Value> = flashcard.fields
+            .iter()
+            .map(|(field, text)| (field.clone(), json!(field_html(text))))
+            .collect();
+        return Value::Object(fields);
+    }
+    
+    if flashcard.is_cloze() {
+        return json!({
+            "Text": cloze_text(flashcard)
+        });
+    }
+    
+    let mut front = field_html(&flashcard.question);
+    if let Some(audio) = &flashcard.audio {
+        front.push_str(&format!("[sound:{}]", audio));
+    }
+    json!({
+        config.front_field.as_str(): front,
+        config.back_field.as_str(): field_html(&flashcard.answer)
+    })
+}
+
+/// Ask Anki which notes can be added; duplicates of existing notes come back false
+pub async fn can_add_notes(notes: &[Value], config: &Config) -> Result<Vec<bool>> {
+    let can_add_data = json!({
+        "action": "canAddNotes",
+        "version": 6,
+        "params": {
+            "notes": notes
+        }
+    });
+    
+    debug!("Checking notes for duplicates: {}", serde_json::to_string_pretty(&can_add_data).unwrap());
+    
+    let response = anki_post(&can_add_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Can add notes response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    let results = response_json["result"]
+        .as_array()
+        .ok_or_else(|| Error::AnkiConnect("no canAddNotes result returned".to_string()))?
+        .iter()
+        .map(|r| r.as_bool().unwrap_or(false))
+        .collect();
+    Ok(results)
+}
+
+/// Escape a value for use inside a quoted Anki search term
+fn escape_anki_search(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '\\' | '"' | '*' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Note id of every note carrying a `note_key_tag`, by that tag
+pub async fn find_keyed_notes(config: &Config) -> Result<HashMap<String, u64>> {
+    let find_notes_data = json!({
+        "action": "findNotes",
+        "version": 6,
+        "params": {
+            "query": format!("\"tag:{}*\"", NOTE_KEY_TAG_PREFIX)
+        }
+    });
+    let response = anki_post(&find_notes_data, config).await?;
+    let response_text = response.text().await?;
+    debug!("Find keyed notes response: {}", response_text);
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    let note_ids = response_json["result"].clone();
+    if note_ids.as_array().is_none_or(|ids| ids.is_empty()) {
+        return Ok(HashMap::new());
+    }
+    
+    // findNotes only returns ids, the tags come from notesInfo
+    let notes_info_data = json!({
+        "action": "notesInfo",
+        "version": 6,
+        "params": {
+            "notes": note_ids
+        }
+    });
+    let response = anki_post(&notes_info_data, config).await?;
+    let response_text = response.text().await?;
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    let mut keyed_notes = HashMap::new();
+    for note in response_json["result"].as_array().into_iter().flatten() {
+        let Some(note_id) = note["noteId"].as_u64() else {
+            continue;
+        };
+        let key_tags = note["tags"].as_array().into_iter().flatten()
+            .filter_map(|tag| tag.as_str())
+            .filter(|tag| tag.starts_with(NOTE_KEY_TAG_PREFIX));
+        for tag in key_tags {
+            keyed_notes.insert(tag.to_string(), note_id);
+        }
+    }
+    debug!("Found {} notes with a key tag", keyed_notes.len());
+    Ok(keyed_notes)
+}
+
+/// Add a tag to an existing note
+pub async fn add_note_tag(note_id: u64, tag: &str, config: &Config) -> Result<()> {
+    let add_tags_data = json!({
+        "action": "addTags",
+        "version": 6,
+        "params": {
+            "notes": [note_id],
+            "tags": tag
+        }
+    });
+    debug!("Adding tag: {}", serde_json::to_string_pretty(&add_tags_data).unwrap());
+    
+    let response = anki_post(&add_tags_data, config).await?;
+    let response_text = response.text().await?;
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if response_json["error"].is_null() {
+        Ok(())
+    } else {
+        Err(Error::AnkiConnect(response_json["error"].to_string()))
+    }
+}
+
+/// Find the note in `deck_name` whose front field matches exactly
+pub async fn find_note_by_front(front: &str, deck_name: &str, config: &Config) -> Result<Option<u64>> {
+    let find_notes_data = json!({
+        "action": "findNotes",
+        "version": 6,
+        "params": {
+            "query": format!(
+                "\"deck:{}\" \"{}:{}\"",
+                escape_anki_search(deck_name),
+                escape_anki_search(&config.front_field),
+                escape_anki_search(&field_html(front))
+            )
+        }
+    });
+    
+    debug!("Finding note by front: {}", serde_json::to_string_pretty(&find_notes_data).unwrap());
+    
+    let response = anki_post(&find_notes_data, config).await?;
+    
+    let response_text = response.text().await?;
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    
+    debug!("Find note by front response: {}", response_text);
+    
+    if !response_json["error"].is_null() {
+        return Err(Error::AnkiConnect(response_json["error"].to_string()));
+    }
+    
+    let note_ids: Vec<u64> = response_json["result"]
+        .as_array()
+        .map(|ids| ids.iter().filter_map(|id| id.as_u64()).collect())
+        .unwrap_or_default();
+    
+    if note_ids.len() > 1 {
+        warn!("{} notes share the front \"{}\", updating only the first", note_ids.len(), front);
+    }
+    Ok(note_ids.first().copied())
+}
+
+/// Overwrite every field of an existing note with the card's content
+pub async fn update_note_fields(note_id: u64, flashcard: &Flashcard, config: &Config) -> Result<()> {
+    let update_data = json!({
+        "action": "updateNoteFields",
+        "version": 6,
+        "params": {
+            "note": {
+                "id": note_id,
+                "fields": note_fields(flashcard, config)
+            }
+        }
+    });
+    
+    debug!("Updating note: {}", serde_json::to_string_pretty(&update_data).unwrap());
+    
+    let response = anki_post(&update_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Update note response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if response_json["error"].is_null() {
+        Ok(())
+    } else {
+        Err(Error::AnkiConnect(response_json["error"].to_string()))
+    }
+}
+
+/// Add all notes with a single addNotes request. Each returned entry is the
+/// new note id, or None when that note could not be added.
+pub async fn add_notes_to_anki(notes: &[Value], config: &Config) -> Result<Vec<Option<u64>>> {
+    let notes_data = json!({
+        "action": "addNotes",
+        "version": 6,
+        "params": {
+            "notes": notes
+        }
+    });
+    
+    debug!("Adding notes to Anki: {}", serde_json::to_string_pretty(&notes_data).unwrap());
+    
+    let response = anki_post(&notes_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Anki-Connect raw response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    
+    debug!("Anki-Connect parsed response: {}", serde_json::to_string_pretty(&response_json)?);
+    
+    // Newer Anki-Connect versions report per-note failures in "error" but
+    // still return the id array, so only fail when there is no result at all
+    match response_json["result"].as_array() {
+        Some(results) => Ok(results.iter().map(|id| id.as_u64()).collect()),
+        None => Err(Error::AnkiConnect(response_json["error"].to_string())),
+    }
+}
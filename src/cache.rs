@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::notion::NotionPage;
+use crate::state::edited_since;
+
+/// Blocks of one page as fetched from Notion, stored as JSON in the cache directory
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedBlocks {
+    /// `last_edited_time` of the page when its blocks were fetched
+    last_edited_time: String,
+    /// When the blocks were fetched
+    fetched_at: String,
+    blocks: Vec<Value>,
+}
+
+fn cache_path(cache_dir: &str, page_id: &str) -> PathBuf {
+    Path::new(cache_dir).join(format!("{}.json", page_id))
+}
+
+/// The cached blocks of a page, unless the page was edited since they were stored.
+/// An unreadable entry counts as missing.
+pub fn load_cached_blocks(cache_dir: &str, page: &NotionPage) -> Option<Vec<Value>> {
+    let content = fs::read_to_string(cache_path(cache_dir, &page.id)).ok()?;
+    let cached: CachedBlocks = match serde_json::from_str(&content) {
+        Ok(cached) => cached,
+        Err(error) => {
+            debug!("Ignoring unreadable cache entry of page {}: {}", page.id, error);
+            return None;
+        },
+    };
+    // An edit in the minute of the fetch doesn't change last_edited_time
+    if cached.last_edited_time != page.last_edited_time || edited_since(&page.last_edited_time, &cached.fetched_at) {
+        debug!("Cached blocks of page {} are outdated", page.id);
+        return None;
+    }
+    debug!("Using cached blocks of page {}", page.id);
+    Some(cached.blocks)
+}
+
+/// Store the fetched blocks of a page. Pages with files hosted by Notion
+/// aren't cached, because the signed file URLs expire within an hour.
+pub fn store_cached_blocks(cache_dir: &str, page: &NotionPage, fetched_at: String, blocks: &[Value]) {
+    if page.last_edited_time.is_empty() || blocks.iter().any(has_notion_file) {
+        return;
+    }
+    let cached = CachedBlocks {
+        last_edited_time: page.last_edited_time.clone(),
+        fetched_at,
+        blocks: blocks.to_vec(),
+    };
+    let result = fs::create_dir_all(cache_dir)
+        .and_then(|_| fs::write(cache_path(cache_dir, &page.id), serde_json::to_string(&cached)?));
+    if let Err(error) = result {
+        warn!("Failed to cache the blocks of page {}: {}", page.id, error);
+    }
+}
+
+/// Whether a block or any of its children holds a Notion-hosted file
+fn has_notion_file(block: &Value) -> bool {
+    let block_type = block["type"].as_str().unwrap_or("");
+    block[block_type]["type"] == "file"
+        || block["children"].as_array().is_some_and(|children| children.iter().any(has_notion_file))
+}
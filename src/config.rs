@@ -0,0 +1,1620 @@
+use clap::{Parser, Subcommand};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::anki::AnkiModels;
+use crate::error::{Error, Result};
+use crate::keychain::read_token;
+use crate::notion::{normalize_page_id, parse_block_url};
+use crate::state::since_timestamp;
+
+/// What to do instead of importing
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check the Notion API key, Anki-Connect, the deck and the note types without changing anything
+    Doctor,
+    /// Ask for the Notion API key and store it in the OS keychain
+    StoreToken,
+}
+
+/// Notion2Anki - Import flashcards from Notion to Anki
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file path (supports JSON or TOML format)
+    #[arg(short, long)]
+    pub config: Option<String>,
+
+    /// Notion API key
+    #[arg(long)]
+    pub notion_api_key: Option<String>,
+
+    /// Anki-Connect URL
+    #[arg(long)]
+    pub anki_connect_url: Option<String>,
+
+    /// Enable or disable debug mode
+    #[arg(long)]
+    pub debug: Option<bool>,
+
+    /// Maximum depth when fetching nested child blocks
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Anki deck to import into (parent deck when DECK_PER_PAGE is true)
+    #[arg(long)]
+    pub deck: Option<String>,
+
+    /// Database multi-select property whose options become the tags of each row's note
+    #[arg(long, value_name = "NAME")]
+    pub tags_property: Option<String>,
+
+    /// Flashcard layout: code, toggle, heading, cloze or inline
+    #[arg(long)]
+    pub card_format: Option<CardFormat>,
+
+    /// Notion page ID to import instead of all pages (repeatable or comma-separated)
+    #[arg(long = "page-id", value_delimiter = ',')]
+    pub page_ids: Vec<String>,
+
+    /// Import only the block this Notion link points to, e.g. a toggle copied with "Copy link to block"
+    #[arg(long = "block-url", value_name = "URL")]
+    pub block_url: Option<String>,
+
+    /// Parse the blocks in this JSON file (written by --dump) instead of fetching pages from Notion
+    #[arg(long = "input", value_name = "FILE")]
+    pub input_path: Option<String>,
+
+    /// Also import the subpages of every page, into subdecks
+    #[arg(long)]
+    pub recurse_subpages: bool,
+
+    /// Exit with an error when some blocks could not be converted
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Write every page and its fetched blocks to DIR/<page id>.page.json and DIR/<page id>.json
+    #[arg(long = "dump", value_name = "DIR")]
+    pub dump_dir: Option<String>,
+
+    /// Update existing notes instead of skipping them as duplicates
+    #[arg(long)]
+    pub update: bool,
+
+    /// Delete every note in the target decks before importing
+    #[arg(long)]
+    pub clear_deck: bool,
+
+    /// Print the cards that would be imported without touching Anki
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Only import pages edited since the last successful run
+    #[arg(long)]
+    pub incremental: bool,
+
+    /// Delete Anki notes whose card was removed from Notion
+    #[arg(long)]
+    pub prune: bool,
+
+    /// Write the cards to an Anki package (.apkg) instead of using Anki-Connect
+    #[arg(long = "export", value_name = "PATH")]
+    pub export_path: Option<String>,
+
+    /// Write the cards to a CSV/TSV file instead of using Anki-Connect
+    #[arg(long = "export-csv", value_name = "PATH")]
+    pub export_csv_path: Option<String>,
+
+    /// Column delimiter of the CSV export (a single character, or `tab`)
+    #[arg(long = "csv-delimiter")]
+    pub csv_delimiter: Option<String>,
+
+    /// Write a JSON line per imported card to this file
+    #[arg(long = "emit-ndjson", value_name = "PATH")]
+    pub emit_ndjson: Option<String>,
+
+    /// Stop at the first card that fails to import
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Stop after adding this many cards in total
+    #[arg(long = "limit", value_name = "N")]
+    pub card_limit: Option<usize>,
+
+    /// Only import pages edited on or after this date, e.g. 2024-01-01
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<String>,
+
+    /// Fetch every page from Notion instead of using the block cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Don't draw the progress bar
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Create a basic note type when Anki has none
+    #[arg(long)]
+    pub create_model: bool,
+
+    /// Summary format: text or json
+    #[arg(long = "report")]
+    pub report_format: Option<ReportFormat>,
+}
+
+/// Application configuration
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Notion API key
+    pub notion_api_key: String,
+    /// Enable detailed debug logging
+    pub debug_mode: bool,
+    /// Anki-Connect URL
+    pub anki_connect_url: String,
+    /// Maximum depth when fetching nested child blocks
+    #[serde(default = "default_max_block_depth")]
+    pub max_block_depth: usize,
+    /// How flashcards are laid out in the Notion page
+    #[serde(default)]
+    pub card_format: CardFormat,
+    /// Whether rich text is rendered as plain text or HTML
+    #[serde(default)]
+    pub text_format: TextFormat,
+    /// Default Anki deck; with per-page decks this becomes the parent deck
+    #[serde(default)]
+    pub deck_name: Option<String>,
+    /// Give each Notion page its own deck named after the page title
+    #[serde(default = "default_deck_per_page")]
+    pub deck_per_page: bool,
+    /// Update existing notes in place instead of skipping them as duplicates
+    #[serde(default)]
+    pub update_mode: bool,
+    /// Delete the notes in each target deck before importing (full update)
+    #[serde(default)]
+    pub clear_deck: bool,
+    /// Also create a swapped Back→Front card for every basic card
+    #[serde(default)]
+    pub reverse_cards: bool,
+    /// Tag added to every imported note; empty disables it
+    #[serde(default = "default_import_tag")]
+    pub import_tag: String,
+    /// Tag notes with the slugified title of their Notion page
+    #[serde(default = "default_tag_page_title")]
+    pub tag_page_title: bool,
+    /// Parse and print cards without sending anything to Anki
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Import only these Notion pages instead of every page the integration can see
+    #[serde(default)]
+    pub page_ids: Vec<String>,
+    /// Import rows of this Notion database as cards instead of page bodies
+    #[serde(default)]
+    pub database_id: Option<String>,
+    /// Database property read into the Front of each card
+    #[serde(default = "default_question_property")]
+    pub question_property: String,
+    /// Database property read into the Back of each card
+    #[serde(default = "default_answer_property")]
+    pub answer_property: String,
+    /// Database multi-select property whose options tag the note of each row
+    #[serde(default)]
+    pub tags_property: Option<String>,
+    /// Database property to Anki field mapping, e.g. `Question:Front,Answer:Back,Example:Extra`
+    #[serde(default)]
+    pub field_map: String,
+    /// How many pages are fetched and parsed at the same time
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Import pages in the order they were found rather than as their fetches finish
+    #[serde(default)]
+    pub preserve_order: bool,
+    /// Skip pages not edited since the last successful run
+    #[serde(default)]
+    pub incremental: bool,
+    /// File remembering the last successful run for incremental sync
+    #[serde(default = "default_state_file")]
+    pub state_file: String,
+    /// Delete notes whose card was removed from Notion
+    #[serde(default)]
+    pub prune: bool,
+    /// Anki-Connect API key, for add-on setups that require one
+    #[serde(default)]
+    pub anki_connect_api_key: Option<String>,
+    /// Write cards to this .apkg file instead of importing them through Anki-Connect
+    #[serde(default)]
+    pub export_path: Option<String>,
+    /// Write cards to this CSV/TSV file instead of importing them through Anki-Connect
+    #[serde(default)]
+    pub export_csv_path: Option<String>,
+    /// Column delimiter of the CSV export: a single character, or `tab`
+    #[serde(default = "default_csv_delimiter")]
+    pub csv_delimiter: String,
+    /// Value of the `Notion-Version` header sent with every Notion request
+    #[serde(default = "default_notion_api_version")]
+    pub notion_api_version: String,
+    /// Base URL of the Notion API, e.g. to go through a proxy
+    #[serde(default = "default_notion_api_url")]
+    pub notion_api_url: String,
+    /// Line that ends the current code-block card, also emitted for Notion dividers; empty disables it
+    #[serde(default = "default_card_separator")]
+    pub card_separator: String,
+    /// Words that start a question line in code-block cards, each followed by `:` or `：`
+    #[serde(default = "default_question_markers")]
+    pub question_markers: Vec<String>,
+    /// Words that start an answer line in code-block cards, each followed by `:` or `：`
+    #[serde(default = "default_answer_markers")]
+    pub answer_markers: Vec<String>,
+    /// How the end-of-run summary is printed
+    #[serde(default)]
+    pub report_format: ReportFormat,
+    /// Abort the run on the first card Anki-Connect fails to add or update
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Seconds before a Notion, Anki-Connect or image request is given up
+    #[serde(default = "default_http_timeout_secs")]
+    pub http_timeout_secs: u64,
+    /// Client shared by all requests, created by `http_client`; not a setting
+    #[serde(skip)]
+    pub client: OnceLock<Client>,
+    /// Earliest time the next Notion request may be sent, for `notion_rate_limit`; not a setting
+    #[serde(skip)]
+    pub notion_next_request: Arc<Mutex<Option<Instant>>>,
+    /// Note types picked by `resolve_models` from the ones Anki has; not a setting
+    #[serde(skip)]
+    pub anki_models: OnceLock<AnkiModels>,
+    /// Id of the `deck_options` group, once `deck_options_id` found or created it; not a setting
+    #[serde(skip)]
+    pub deck_options_group: OnceLock<u64>,
+    /// Set once Notion refused to show comments to the integration; not a setting
+    #[serde(skip)]
+    pub comments_unreadable: OnceLock<()>,
+    /// Note type for basic cards, before the localized names are tried
+    #[serde(default = "default_model_name")]
+    pub model_name: String,
+    /// Note type for cloze cards, before the localized names are tried
+    #[serde(default = "default_cloze_model_name")]
+    pub cloze_model_name: String,
+    /// Note type field that holds the question of basic cards
+    #[serde(default = "default_front_field")]
+    pub front_field: String,
+    /// Note type field that holds the answer of basic cards
+    #[serde(default = "default_back_field")]
+    pub back_field: String,
+    /// Note type field that gets a link to the card's Notion page, when the note type has it
+    #[serde(default = "default_source_field_option")]
+    pub source_field: Option<String>,
+    /// Stop adding notes once this many were added in the run
+    #[serde(default)]
+    pub card_limit: Option<usize>,
+    /// Import only pages whose title matches this text, see `page_title_match`
+    #[serde(default)]
+    pub page_title_filter: Option<String>,
+    /// Whether `page_title_filter` must start the title or only appear in it
+    #[serde(default)]
+    pub page_title_match: TitleMatch,
+    /// Show a progress bar over the pages when stderr is a terminal
+    #[serde(default = "default_progress")]
+    pub progress: bool,
+    /// Log filter from `RUST_LOG`, which takes precedence over `debug_mode`
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// Add speech of the Front, synthesized by `tts_url`, to basic notes
+    #[serde(default)]
+    pub tts_enabled: bool,
+    /// Text-to-speech endpoint, requested with the text in a `text` query parameter
+    #[serde(default)]
+    pub tts_url: Option<String>,
+    /// Reuse the blocks of pages not edited since they were cached in `cache_dir`
+    #[serde(default)]
+    pub block_cache: bool,
+    /// Directory of the block cache
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+    /// Import only the block this Notion link points to (`...#<block id>`), with everything in it
+    #[serde(default)]
+    pub block_url: Option<String>,
+    /// Most Notion requests sent per second, shared by all concurrent fetches; 0 disables the limit
+    #[serde(default = "default_notion_rate_limit")]
+    pub notion_rate_limit: f64,
+    /// Front of basic cards built from `{title}`, `{front}` and `{back}`, e.g. `[{title}] {front}`
+    #[serde(default)]
+    pub front_template: Option<String>,
+    /// Back of basic cards built from `{title}`, `{front}` and `{back}`
+    #[serde(default)]
+    pub back_template: Option<String>,
+    /// Create a basic note type when Anki has none of the usual ones
+    #[serde(default)]
+    pub create_model: bool,
+    /// Only import pages edited on or after this date (`2024-01-01`) or RFC 3339 time
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Top-level `# Heading`s of a page name the deck of the cards after them
+    #[serde(default)]
+    pub deck_headings: bool,
+    /// Times a request Anki-Connect refused to connect for is sent again
+    #[serde(default = "default_anki_connect_retries")]
+    pub anki_connect_retries: u32,
+    /// JSON file of blocks, as written by `dump_dir`, to parse instead of fetching pages from Notion
+    #[serde(default)]
+    pub input_path: Option<String>,
+    /// Directory every page (`<page id>.page.json`) and its fetched blocks (`<page id>.json`) are written to
+    #[serde(default)]
+    pub dump_dir: Option<String>,
+    /// Also import the subpages behind `child_page` blocks, into subdecks
+    #[serde(default)]
+    pub recurse_subpages: bool,
+    /// Joins parent and child deck names; Anki only nests decks at `::`
+    #[serde(default = "default_deck_separator")]
+    pub deck_separator: String,
+    /// Name the deck of a page after the pages above it, joined by `deck_separator`
+    #[serde(default)]
+    pub deck_hierarchy: bool,
+    /// How equations are shown: MathJax LaTeX, or images rendered by `equation_render_url`
+    #[serde(default)]
+    pub equation_mode: EquationMode,
+    /// LaTeX rendering endpoint, requested with the expression in a `latex` query parameter
+    #[serde(default)]
+    pub equation_render_url: Option<String>,
+    /// Fail the run when some blocks have no conversion and were left out of the cards
+    #[serde(default)]
+    pub strict: bool,
+    /// Read the Notion API key from the OS keychain entry `keychain_service`/`keychain_account`
+    #[serde(default)]
+    pub keychain: bool,
+    /// Service name of the keychain entry holding the Notion API key
+    #[serde(default = "default_keychain_service")]
+    pub keychain_service: String,
+    /// Account name of the keychain entry holding the Notion API key
+    #[serde(default = "default_keychain_account")]
+    pub keychain_account: String,
+    /// Which repeats of a card within a deck are dropped before they reach Anki
+    #[serde(default)]
+    pub dedupe_cards: CardDedupe,
+    /// Longest answer a parsed card may have, in characters; see `long_answer`
+    #[serde(default)]
+    pub max_answer_chars: Option<usize>,
+    /// Whether a card over `max_answer_chars` is skipped or has its answer cut off
+    #[serde(default)]
+    pub long_answer: LongAnswer,
+    /// Render quote blocks as a collapsed hint that opens when clicked
+    #[serde(default)]
+    pub quote_as_hint: bool,
+    /// Whether the search imports pages, databases or both
+    #[serde(default)]
+    pub search_objects: SearchObjects,
+    /// Options group (presets) that decks created by the import are set to
+    #[serde(default)]
+    pub deck_options: Option<String>,
+    /// Append the Notion comments on the block behind a card to its answer
+    #[serde(default)]
+    pub block_comments: bool,
+    /// Write a JSON line per card imported (or previewed) to this file
+    #[serde(default)]
+    pub emit_ndjson: Option<String>,
+    /// What splits the question from the answer of a line with `CardFormat::Inline`
+    #[serde(default = "default_inline_delimiter")]
+    pub inline_delimiter: String,
+}
+
+fn default_max_block_depth() -> usize {
+    5
+}
+
+fn default_anki_connect_retries() -> u32 {
+    3
+}
+
+fn default_deck_separator() -> String {
+    "::".to_string()
+}
+
+fn default_inline_delimiter() -> String {
+    "::".to_string()
+}
+
+fn default_keychain_service() -> String {
+    "notion2anki".to_string()
+}
+
+fn default_keychain_account() -> String {
+    "NOTION_API_KEY".to_string()
+}
+
+fn default_deck_per_page() -> bool {
+    true
+}
+
+fn default_import_tag() -> String {
+    "notion-import".to_string()
+}
+
+fn default_tag_page_title() -> bool {
+    true
+}
+
+fn default_concurrency() -> usize {
+    3
+}
+
+fn default_state_file() -> String {
+    ".notion2anki.json".to_string()
+}
+
+fn default_question_property() -> String {
+    "Question".to_string()
+}
+
+fn default_question_markers() -> Vec<String> {
+    vec!["问题".to_string(), "Question".to_string()]
+}
+
+fn default_answer_markers() -> Vec<String> {
+    vec!["答案".to_string(), "Answer".to_string(), "回答".to_string()]
+}
+
+fn default_model_name() -> String {
+    "Basic".to_string()
+}
+
+fn default_cloze_model_name() -> String {
+    "Cloze".to_string()
+}
+
+fn default_front_field() -> String {
+    "Front".to_string()
+}
+
+fn default_back_field() -> String {
+    "Back".to_string()
+}
+
+fn default_source_field() -> String {
+    "Source".to_string()
+}
+
+fn default_source_field_option() -> Option<String> {
+    Some(default_source_field())
+}
+
+fn default_cache_dir() -> String {
+    ".notion2anki-cache".to_string()
+}
+
+fn default_notion_rate_limit() -> f64 {
+    3.0
+}
+
+fn default_progress() -> bool {
+    true
+}
+
+fn default_http_timeout_secs() -> u64 {
+    30
+}
+
+fn default_card_separator() -> String {
+    "---".to_string()
+}
+
+fn default_notion_api_version() -> String {
+    "2022-06-28".to_string()
+}
+
+fn default_notion_api_url() -> String {
+    "https://api.notion.com/v1".to_string()
+}
+
+fn default_csv_delimiter() -> String {
+    ",".to_string()
+}
+
+fn default_answer_property() -> String {
+    "Answer".to_string()
+}
+
+/// Name of the configuration file looked for when `--config` isn't given
+const DEFAULT_CONFIG_FILE: &str = "notion2anki.toml";
+
+/// `notion2anki.toml` in the working directory, else in `~/.config/notion2anki/`
+fn default_config_path() -> Option<String> {
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok();
+    let candidates = [
+        Some(Path::new(DEFAULT_CONFIG_FILE).to_path_buf()),
+        home.map(|home| Path::new(&home).join(".config").join("notion2anki").join(DEFAULT_CONFIG_FILE)),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Split a comma-separated environment value into its non-empty items
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+/// Flashcard layout recognized by the parser
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardFormat {
+    /// `问题:`/`答案:` lines inside fenced code blocks
+    #[default]
+    Code,
+    /// Toggle text is the question, its children are the answer
+    Toggle,
+    /// A heading is the question, the blocks up to the next heading are the answer
+    Heading,
+    /// Every line with `{{c1::...}}` deletions becomes a cloze card
+    Cloze,
+    /// Every line with `inline_delimiter` becomes a card: the question before it, the answer after
+    Inline,
+}
+
+/// Output of `extract_rich_text`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextFormat {
+    /// Concatenated `plain_text`, annotations dropped
+    #[default]
+    Plain,
+    /// Annotations rendered as HTML tags for Anki fields
+    Html,
+}
+
+impl std::str::FromStr for TextFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(TextFormat::Plain),
+            "html" => Ok(TextFormat::Html),
+            _ => Err(format!("Invalid TEXT_FORMAT value: {} (expected plain or html)", s)),
+        }
+    }
+}
+
+/// Format of the summary printed at the end of a run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// Counts, a line per page and the failed cards, for reading
+    #[default]
+    Text,
+    /// The whole summary as one line of JSON, for scripts
+    Json,
+}
+
+/// How equations end up on cards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EquationMode {
+    /// `\(...\)` and `\[...\]` LaTeX, typeset by Anki's MathJax
+    #[default]
+    Mathjax,
+    /// Images rendered by `equation_render_url` and stored in Anki's media folder
+    Image,
+}
+
+impl std::str::FromStr for EquationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mathjax" => Ok(EquationMode::Mathjax),
+            "image" => Ok(EquationMode::Image),
+            _ => Err(format!("Invalid EQUATION_MODE value: {} (expected mathjax or image)", s)),
+        }
+    }
+}
+
+/// Which cards of a deck are the same card for `DEDUPE_CARDS`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CardDedupe {
+    /// Same Front and Back
+    #[default]
+    FrontBack,
+    /// Same Front, whatever the Back
+    Front,
+    /// Every card is kept
+    Off,
+}
+
+impl std::str::FromStr for CardDedupe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "front-back" => Ok(CardDedupe::FrontBack),
+            "front" => Ok(CardDedupe::Front),
+            "off" => Ok(CardDedupe::Off),
+            _ => Err(format!("Invalid DEDUPE_CARDS value: {} (expected front-back, front or off)", s)),
+        }
+    }
+}
+
+/// What happens to a card whose answer is longer than `MAX_ANSWER_CHARS`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LongAnswer {
+    /// The card is left out with a warning
+    #[default]
+    Skip,
+    /// The answer is cut off with an ellipsis
+    Truncate,
+}
+
+impl std::str::FromStr for LongAnswer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(LongAnswer::Skip),
+            "truncate" => Ok(LongAnswer::Truncate),
+            _ => Err(format!("Invalid LONG_ANSWER value: {} (expected skip or truncate)", s)),
+        }
+    }
+}
+
+/// What the search for everything shared with the integration looks for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchObjects {
+    /// Pages, whose blocks hold the cards
+    #[default]
+    Pages,
+    /// Databases, whose rows are the cards
+    Databases,
+    /// Both; database rows are only imported as rows
+    Both,
+}
+
+impl SearchObjects {
+    pub fn includes_pages(self) -> bool {
+        self != SearchObjects::Databases
+    }
+
+    pub fn includes_databases(self) -> bool {
+        self != SearchObjects::Pages
+    }
+}
+
+impl std::str::FromStr for SearchObjects {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pages" => Ok(SearchObjects::Pages),
+            "databases" => Ok(SearchObjects::Databases),
+            "both" => Ok(SearchObjects::Both),
+            _ => Err(format!("Invalid SEARCH_OBJECTS value: {} (expected pages, databases or both)", s)),
+        }
+    }
+}
+
+/// How `PAGE_TITLE_FILTER` is matched against page titles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleMatch {
+    /// The title starts with the filter
+    #[default]
+    Prefix,
+    /// The filter appears anywhere in the title
+    Contains,
+}
+
+impl std::str::FromStr for TitleMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "prefix" => Ok(TitleMatch::Prefix),
+            "contains" => Ok(TitleMatch::Contains),
+            _ => Err(format!("Invalid PAGE_TITLE_MATCH value: {} (expected prefix or contains)", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("Invalid REPORT_FORMAT value: {} (expected text or json)", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for CardFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "code" => Ok(CardFormat::Code),
+            "toggle" => Ok(CardFormat::Toggle),
+            "heading" => Ok(CardFormat::Heading),
+            "cloze" => Ok(CardFormat::Cloze),
+            "inline" => Ok(CardFormat::Inline),
+            _ => Err(format!("Invalid CARD_FORMAT value: {} (expected code, toggle, heading, cloze or inline)", s)),
+        }
+    }
+}
+
+/// Settings whose value `Debug` leaves out, so debug logs don't show the keys
+const SECRET_SETTINGS: [&str; 2] = ["notion_api_key", "anki_connect_api_key"];
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The settings as serialized, which leaves out the state of the run
+        let Value::Object(settings) = serde_json::to_value(self).map_err(|_| std::fmt::Error)? else {
+            return Err(std::fmt::Error);
+        };
+        let mut debug = f.debug_struct("Config");
+        for (name, value) in &settings {
+            let is_set = !value.is_null() && value != "";
+            if is_set && SECRET_SETTINGS.contains(&name.as_str()) {
+                debug.field(name, &format_args!("<redacted>"));
+            } else {
+                debug.field(name, &format_args!("{}", value));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            notion_api_key: String::new(),
+            debug_mode: false,
+            anki_connect_url: "http://localhost:8765".to_string(),
+            max_block_depth: default_max_block_depth(),
+            card_format: CardFormat::default(),
+            text_format: TextFormat::default(),
+            deck_name: None,
+            deck_per_page: default_deck_per_page(),
+            update_mode: false,
+            clear_deck: false,
+            reverse_cards: false,
+            import_tag: default_import_tag(),
+            tag_page_title: default_tag_page_title(),
+            dry_run: false,
+            page_ids: Vec::new(),
+            database_id: None,
+            question_property: default_question_property(),
+            answer_property: default_answer_property(),
+            tags_property: None,
+            field_map: String::new(),
+            concurrency: default_concurrency(),
+            preserve_order: false,
+            incremental: false,
+            state_file: default_state_file(),
+            prune: false,
+            anki_connect_api_key: None,
+            export_path: None,
+            export_csv_path: None,
+            csv_delimiter: default_csv_delimiter(),
+            notion_api_version: default_notion_api_version(),
+            notion_api_url: default_notion_api_url(),
+            card_separator: default_card_separator(),
+            question_markers: default_question_markers(),
+            answer_markers: default_answer_markers(),
+            report_format: ReportFormat::default(),
+            fail_fast: false,
+            http_timeout_secs: default_http_timeout_secs(),
+            client: OnceLock::new(),
+            notion_next_request: Arc::default(),
+            anki_models: OnceLock::new(),
+            deck_options_group: OnceLock::new(),
+            comments_unreadable: OnceLock::new(),
+            model_name: default_model_name(),
+            cloze_model_name: default_cloze_model_name(),
+            front_field: default_front_field(),
+            back_field: default_back_field(),
+            source_field: default_source_field_option(),
+            card_limit: None,
+            page_title_filter: None,
+            page_title_match: TitleMatch::default(),
+            progress: default_progress(),
+            log_filter: None,
+            tts_enabled: false,
+            tts_url: None,
+            block_cache: false,
+            cache_dir: default_cache_dir(),
+            block_url: None,
+            notion_rate_limit: default_notion_rate_limit(),
+            front_template: None,
+            back_template: None,
+            create_model: false,
+            since: None,
+            deck_headings: false,
+            anki_connect_retries: default_anki_connect_retries(),
+            input_path: None,
+            dump_dir: None,
+            recurse_subpages: false,
+            deck_separator: default_deck_separator(),
+            deck_hierarchy: false,
+            equation_mode: EquationMode::default(),
+            equation_render_url: None,
+            strict: false,
+            keychain: false,
+            keychain_service: default_keychain_service(),
+            keychain_account: default_keychain_account(),
+            dedupe_cards: CardDedupe::default(),
+            max_answer_chars: None,
+            long_answer: LongAnswer::default(),
+            quote_as_hint: false,
+            search_objects: SearchObjects::default(),
+            deck_options: None,
+            block_comments: false,
+            emit_ndjson: None,
+            inline_delimiter: default_inline_delimiter(),
+        }
+    }
+}
+
+impl Config {
+    /// Create configuration from command line arguments and environment variables
+    /// Priority: CLI args > Config file > Environment variables > notion2anki.toml > Default values
+    /// Each setting comes from the highest source that sets it, so environment
+    /// variables (and `.env`) act as fallbacks for the file and the CLI
+    pub fn from_args_and_env(args: &Args) -> Result<Self> {
+        let config = Self::from_sources(args)?;
+
+        // Validate required parameters
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Settings from every source, before they are validated. `store-token`
+    /// uses this, because it runs before there is a key.
+    pub fn from_sources(args: &Args) -> Result<Self> {
+        let mut config = Config::default();
+
+        // Priority 4: A notion2anki.toml found in a default location, below the
+        // environment so env vars can override a versioned setup
+        if args.config.is_none() {
+            if let Some(default_path) = default_config_path() {
+                config = config.merge_file(&default_path)?;
+            }
+        }
+
+        // Priority 3: Environment variables
+        config.apply_env()?;
+
+        // Priority 2: Configuration file, only for the keys it contains
+        if let Some(config_path) = &args.config {
+            config = config.merge_file(config_path)?;
+        }
+
+        // The keychain entry stands in for a key kept in the environment or a file
+        if config.keychain {
+            if let Some(token) = read_token(&config.keychain_service, &config.keychain_account) {
+                config.notion_api_key = token;
+            }
+        }
+
+        // Priority 1: Command line arguments (highest priority)
+        config.apply_args(args);
+
+        Ok(config)
+    }
+
+    /// Override settings with the environment variables that are set
+    fn apply_env(&mut self) -> Result<()> {
+        if let Ok(notion_key) = env::var("NOTION_API_KEY") {
+            self.notion_api_key = notion_key;
+        }
+        if let Ok(anki_url) = env::var("ANKI_CONNECT_URL") {
+            self.anki_connect_url = anki_url;
+        }
+        if let Ok(debug_mode) = env::var("DEBUG_MODE") {
+            self.debug_mode = debug_mode.to_lowercase() == "true";
+        }
+        if let Ok(max_depth) = env::var("MAX_BLOCK_DEPTH") {
+            self.max_block_depth = max_depth.parse()
+                .map_err(|_| Error::Config(format!("Invalid MAX_BLOCK_DEPTH value: {}", max_depth)))?;
+        }
+        if let Ok(card_format) = env::var("CARD_FORMAT") {
+            self.card_format = card_format.parse().map_err(Error::Config)?;
+        }
+        if let Ok(text_format) = env::var("TEXT_FORMAT") {
+            self.text_format = text_format.parse().map_err(Error::Config)?;
+        }
+        if let Ok(deck_name) = env::var("ANKI_DECK_NAME") {
+            self.deck_name = Some(deck_name).filter(|name| !name.is_empty());
+        }
+        if let Ok(deck_per_page) = env::var("DECK_PER_PAGE") {
+            self.deck_per_page = deck_per_page.to_lowercase() == "true";
+        }
+        if let Ok(update_mode) = env::var("UPDATE_MODE") {
+            self.update_mode = update_mode.to_lowercase() == "true";
+        }
+        if let Ok(clear_deck) = env::var("CLEAR_DECK") {
+            self.clear_deck = clear_deck.to_lowercase() == "true";
+        }
+        if let Ok(reverse_cards) = env::var("REVERSE_CARDS") {
+            self.reverse_cards = reverse_cards.to_lowercase() == "true";
+        }
+        if let Ok(import_tag) = env::var("NOTION_IMPORT_TAG") {
+            self.import_tag = import_tag;
+        }
+        if let Ok(tag_page_title) = env::var("TAG_PAGE_TITLE") {
+            self.tag_page_title = tag_page_title.to_lowercase() == "true";
+        }
+        if let Ok(dry_run) = env::var("DRY_RUN") {
+            self.dry_run = dry_run.to_lowercase() == "true";
+        }
+        if let Ok(page_ids) = env::var("NOTION_PAGE_IDS") {
+            self.page_ids = split_list(&page_ids);
+        }
+        if let Ok(database_id) = env::var("NOTION_DATABASE_ID") {
+            self.database_id = Some(database_id).filter(|value| !value.is_empty());
+        }
+        if let Ok(question_property) = env::var("NOTION_QUESTION_PROPERTY") {
+            self.question_property = question_property;
+        }
+        if let Ok(answer_property) = env::var("NOTION_ANSWER_PROPERTY") {
+            self.answer_property = answer_property;
+        }
+        if let Ok(tags_property) = env::var("NOTION_TAGS_PROPERTY") {
+            self.tags_property = Some(tags_property).filter(|value| !value.is_empty());
+        }
+        if let Ok(field_map) = env::var("FIELD_MAP") {
+            self.field_map = field_map;
+        }
+        if let Ok(concurrency) = env::var("CONCURRENCY") {
+            self.concurrency = concurrency.parse()
+                .map_err(|_| Error::Config(format!("Invalid CONCURRENCY value: {}", concurrency)))?;
+        }
+        if let Ok(preserve_order) = env::var("PRESERVE_ORDER") {
+            self.preserve_order = preserve_order.to_lowercase() == "true";
+        }
+        if let Ok(incremental) = env::var("INCREMENTAL_SYNC") {
+            self.incremental = incremental.to_lowercase() == "true";
+        }
+        if let Ok(state_file) = env::var("STATE_FILE") {
+            self.state_file = state_file;
+        }
+        if let Ok(prune) = env::var("PRUNE") {
+            self.prune = prune.to_lowercase() == "true";
+        }
+        if let Ok(anki_connect_api_key) = env::var("ANKI_CONNECT_API_KEY") {
+            self.anki_connect_api_key = Some(anki_connect_api_key).filter(|value| !value.is_empty());
+        }
+        if let Ok(export_path) = env::var("EXPORT_PATH") {
+            self.export_path = Some(export_path).filter(|value| !value.is_empty());
+        }
+        if let Ok(export_csv_path) = env::var("EXPORT_CSV_PATH") {
+            self.export_csv_path = Some(export_csv_path).filter(|value| !value.is_empty());
+        }
+        if let Ok(csv_delimiter) = env::var("CSV_DELIMITER") {
+            self.csv_delimiter = csv_delimiter;
+        }
+        if let Ok(notion_api_version) = env::var("NOTION_API_VERSION") {
+            self.notion_api_version = notion_api_version;
+        }
+        if let Ok(notion_api_url) = env::var("NOTION_API_URL") {
+            self.notion_api_url = notion_api_url.trim_end_matches('/').to_string();
+        }
+        if let Ok(card_separator) = env::var("CARD_SEPARATOR") {
+            self.card_separator = card_separator;
+        }
+        if let Ok(question_markers) = env::var("QUESTION_MARKERS") {
+            self.question_markers = split_list(&question_markers);
+        }
+        if let Ok(answer_markers) = env::var("ANSWER_MARKERS") {
+            self.answer_markers = split_list(&answer_markers);
+        }
+        if let Ok(report_format) = env::var("REPORT_FORMAT") {
+            self.report_format = report_format.parse().map_err(Error::Config)?;
+        }
+        if let Ok(fail_fast) = env::var("FAIL_FAST") {
+            self.fail_fast = fail_fast.to_lowercase() == "true";
+        }
+        if let Ok(http_timeout_secs) = env::var("HTTP_TIMEOUT_SECS") {
+            self.http_timeout_secs = http_timeout_secs.parse()
+                .map_err(|_| Error::Config(format!("Invalid HTTP_TIMEOUT_SECS value: {}", http_timeout_secs)))?;
+        }
+        if let Ok(model_name) = env::var("ANKI_MODEL_NAME") {
+            self.model_name = model_name;
+        }
+        if let Ok(cloze_model_name) = env::var("ANKI_CLOZE_MODEL_NAME") {
+            self.cloze_model_name = cloze_model_name;
+        }
+        if let Ok(front_field) = env::var("ANKI_FRONT_FIELD") {
+            self.front_field = front_field;
+        }
+        if let Ok(back_field) = env::var("ANKI_BACK_FIELD") {
+            self.back_field = back_field;
+        }
+        if let Ok(source_field) = env::var("ANKI_SOURCE_FIELD") {
+            self.source_field = Some(source_field).filter(|value| !value.is_empty());
+        }
+        if let Ok(card_limit) = env::var("CARD_LIMIT") {
+            self.card_limit = Some(card_limit.parse()
+                .map_err(|_| Error::Config(format!("Invalid CARD_LIMIT value: {}", card_limit)))?);
+        }
+        if let Ok(page_title_filter) = env::var("PAGE_TITLE_FILTER") {
+            self.page_title_filter = Some(page_title_filter).filter(|value| !value.is_empty());
+        }
+        if let Ok(page_title_match) = env::var("PAGE_TITLE_MATCH") {
+            self.page_title_match = page_title_match.parse().map_err(Error::Config)?;
+        }
+        if let Ok(progress) = env::var("SHOW_PROGRESS") {
+            self.progress = progress.to_lowercase() == "true";
+        }
+        if let Ok(log_filter) = env::var("RUST_LOG") {
+            self.log_filter = Some(log_filter).filter(|value| !value.is_empty());
+        }
+        if let Ok(tts_enabled) = env::var("TTS_ENABLED") {
+            self.tts_enabled = tts_enabled.to_lowercase() == "true";
+        }
+        if let Ok(tts_url) = env::var("TTS_URL") {
+            self.tts_url = Some(tts_url).filter(|value| !value.is_empty());
+        }
+        if let Ok(block_cache) = env::var("BLOCK_CACHE") {
+            self.block_cache = block_cache.to_lowercase() == "true";
+        }
+        if let Ok(cache_dir) = env::var("CACHE_DIR") {
+            self.cache_dir = cache_dir;
+        }
+        if let Ok(block_url) = env::var("NOTION_BLOCK_URL") {
+            self.block_url = Some(block_url).filter(|value| !value.is_empty());
+        }
+        if let Ok(notion_rate_limit) = env::var("NOTION_RATE_LIMIT") {
+            self.notion_rate_limit = notion_rate_limit.parse()
+                .map_err(|_| Error::Config(format!("Invalid NOTION_RATE_LIMIT value: {}", notion_rate_limit)))?;
+        }
+        if let Ok(front_template) = env::var("FRONT_TEMPLATE") {
+            self.front_template = Some(front_template).filter(|value| !value.is_empty());
+        }
+        if let Ok(back_template) = env::var("BACK_TEMPLATE") {
+            self.back_template = Some(back_template).filter(|value| !value.is_empty());
+        }
+        if let Ok(create_model) = env::var("CREATE_MODEL") {
+            self.create_model = create_model.to_lowercase() == "true";
+        }
+        if let Ok(since) = env::var("EDITED_SINCE") {
+            self.since = Some(since).filter(|value| !value.is_empty());
+        }
+        if let Ok(deck_headings) = env::var("DECK_HEADINGS") {
+            self.deck_headings = deck_headings.to_lowercase() == "true";
+        }
+        if let Ok(anki_connect_retries) = env::var("ANKI_CONNECT_RETRIES") {
+            self.anki_connect_retries = anki_connect_retries.parse()
+                .map_err(|_| Error::Config(format!("Invalid ANKI_CONNECT_RETRIES value: {}", anki_connect_retries)))?;
+        }
+        if let Ok(input_path) = env::var("BLOCKS_INPUT") {
+            self.input_path = Some(input_path).filter(|value| !value.is_empty());
+        }
+        if let Ok(dump_dir) = env::var("BLOCKS_DUMP_DIR") {
+            self.dump_dir = Some(dump_dir).filter(|value| !value.is_empty());
+        }
+        if let Ok(recurse_subpages) = env::var("RECURSE_SUBPAGES") {
+            self.recurse_subpages = recurse_subpages.to_lowercase() == "true";
+        }
+        if let Ok(deck_separator) = env::var("DECK_SEPARATOR") {
+            self.deck_separator = deck_separator;
+        }
+        if let Ok(deck_hierarchy) = env::var("DECK_HIERARCHY") {
+            self.deck_hierarchy = deck_hierarchy.to_lowercase() == "true";
+        }
+        if let Ok(equation_mode) = env::var("EQUATION_MODE") {
+            self.equation_mode = equation_mode.parse().map_err(Error::Config)?;
+        }
+        if let Ok(equation_render_url) = env::var("EQUATION_RENDER_URL") {
+            self.equation_render_url = Some(equation_render_url).filter(|value| !value.is_empty());
+        }
+        if let Ok(strict) = env::var("STRICT") {
+            self.strict = strict.to_lowercase() == "true";
+        }
+        if let Ok(keychain) = env::var("KEYCHAIN") {
+            self.keychain = keychain.to_lowercase() == "true";
+        }
+        if let Ok(keychain_service) = env::var("KEYCHAIN_SERVICE") {
+            self.keychain_service = keychain_service;
+        }
+        if let Ok(keychain_account) = env::var("KEYCHAIN_ACCOUNT") {
+            self.keychain_account = keychain_account;
+        }
+        if let Ok(dedupe_cards) = env::var("DEDUPE_CARDS") {
+            self.dedupe_cards = dedupe_cards.parse().map_err(Error::Config)?;
+        }
+        if let Ok(max_answer_chars) = env::var("MAX_ANSWER_CHARS") {
+            self.max_answer_chars = Some(max_answer_chars.parse()
+                .map_err(|_| Error::Config(format!("Invalid MAX_ANSWER_CHARS value: {}", max_answer_chars)))?);
+        }
+        if let Ok(long_answer) = env::var("LONG_ANSWER") {
+            self.long_answer = long_answer.parse().map_err(Error::Config)?;
+        }
+        if let Ok(quote_as_hint) = env::var("QUOTE_AS_HINT") {
+            self.quote_as_hint = quote_as_hint.to_lowercase() == "true";
+        }
+        if let Ok(search_objects) = env::var("SEARCH_OBJECTS") {
+            self.search_objects = search_objects.parse().map_err(Error::Config)?;
+        }
+        if let Ok(deck_options) = env::var("DECK_OPTIONS") {
+            self.deck_options = Some(deck_options).filter(|value| !value.is_empty());
+        }
+        if let Ok(block_comments) = env::var("BLOCK_COMMENTS") {
+            self.block_comments = block_comments.to_lowercase() == "true";
+        }
+        if let Ok(emit_ndjson) = env::var("EMIT_NDJSON") {
+            self.emit_ndjson = Some(emit_ndjson).filter(|value| !value.is_empty());
+        }
+        if let Ok(inline_delimiter) = env::var("INLINE_DELIMITER") {
+            self.inline_delimiter = inline_delimiter;
+        }
+        Ok(())
+    }
+
+    /// Override settings with the keys present in a config file
+    fn merge_file(self, path: &str) -> Result<Self> {
+        let mut merged = serde_json::to_value(&self)?;
+        if let (Value::Object(merged), Value::Object(file)) = (&mut merged, Self::read_file(path)?) {
+            merged.extend(file);
+        }
+        serde_json::from_value(merged)
+            .map_err(|e| Error::Config(format!("Invalid configuration file {}: {}", path, e)))
+    }
+
+    /// Override settings with the command line arguments that were given
+    fn apply_args(&mut self, args: &Args) {
+        if let Some(notion_key) = &args.notion_api_key {
+            self.notion_api_key = notion_key.clone();
+        }
+        if let Some(anki_url) = &args.anki_connect_url {
+            self.anki_connect_url = anki_url.clone();
+        }
+        if let Some(debug_value) = args.debug {
+            self.debug_mode = debug_value;
+        }
+        if let Some(max_depth) = args.max_depth {
+            self.max_block_depth = max_depth;
+        }
+        if let Some(deck_name) = &args.deck {
+            self.deck_name = Some(deck_name.clone()).filter(|name| !name.is_empty());
+        }
+        if let Some(tags_property) = &args.tags_property {
+            self.tags_property = Some(tags_property.clone()).filter(|name| !name.is_empty());
+        }
+        if let Some(card_format) = args.card_format {
+            self.card_format = card_format;
+        }
+        if !args.page_ids.is_empty() {
+            self.page_ids = args.page_ids.clone();
+        }
+        if let Some(block_url) = &args.block_url {
+            self.block_url = Some(block_url.clone());
+        }
+        if args.recurse_subpages {
+            self.recurse_subpages = true;
+        }
+        if args.strict {
+            self.strict = true;
+        }
+        if let Some(input_path) = &args.input_path {
+            self.input_path = Some(input_path.clone());
+        }
+        if let Some(dump_dir) = &args.dump_dir {
+            self.dump_dir = Some(dump_dir.clone());
+        }
+        if args.update {
+            self.update_mode = true;
+        }
+        if args.clear_deck {
+            self.clear_deck = true;
+        }
+        if args.dry_run {
+            self.dry_run = true;
+        }
+        if args.incremental {
+            self.incremental = true;
+        }
+        if args.prune {
+            self.prune = true;
+        }
+        if let Some(export_path) = &args.export_path {
+            self.export_path = Some(export_path.clone());
+        }
+        if let Some(export_csv_path) = &args.export_csv_path {
+            self.export_csv_path = Some(export_csv_path.clone());
+        }
+        if let Some(csv_delimiter) = &args.csv_delimiter {
+            self.csv_delimiter = csv_delimiter.clone();
+        }
+        if let Some(emit_ndjson) = &args.emit_ndjson {
+            self.emit_ndjson = Some(emit_ndjson.clone());
+        }
+        if args.fail_fast {
+            self.fail_fast = true;
+        }
+        if let Some(card_limit) = args.card_limit {
+            self.card_limit = Some(card_limit);
+        }
+        if let Some(since) = &args.since {
+            self.since = Some(since.clone());
+        }
+        if args.no_cache {
+            self.block_cache = false;
+        }
+        if args.no_progress {
+            self.progress = false;
+        }
+        if args.create_model {
+            self.create_model = true;
+        }
+        if let Some(report_format) = args.report_format {
+            self.report_format = report_format;
+        }
+    }
+
+    /// Load configuration from file
+    pub fn load_from_file(path: &str) -> Result<Self> {
+        Config::default().merge_file(path)
+    }
+
+    /// Read a JSON or TOML config file into a JSON object
+    fn read_file(path: &str) -> Result<Value> {
+        let path = Path::new(path);
+        if !path.exists() {
+            return Err(Error::Config(format!("Configuration file does not exist: {}", path.display())));
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("Failed to read configuration file: {}", e)))?;
+
+        if path.extension().and_then(|s| s.to_str()) == Some("toml") {
+            toml::from_str(&content)
+                .map_err(|e| Error::Config(format!("Failed to parse TOML configuration file: {}", e)))
+        } else {
+            serde_json::from_str(&content)
+                .map_err(|e| Error::Config(format!("Failed to parse JSON configuration file: {}", e)))
+        }
+    }
+
+    /// HTTP client for Notion, Anki-Connect and image downloads, with the request
+    /// timeout. It's created on first use and shared by the whole run, so
+    /// connections are pooled across requests.
+    pub fn http_client(&self) -> &Client {
+        self.client.get_or_init(|| {
+            Client::builder()
+                .timeout(Duration::from_secs(self.http_timeout_secs))
+                .build()
+                .expect("failed to initialize the HTTP client")
+        })
+    }
+
+    /// Whether the decks of the pages are cleared before their cards are
+    /// imported. `unchanged_pages` were left out by incremental sync.
+    pub fn clears_decks(&self, unchanged_pages: usize) -> bool {
+        // A deck of its own holds only the page's cards, but decks named by
+        // headings can be shared too
+        let own_decks = self.deck_per_page && !self.deck_headings;
+        // A shared deck also holds the cards of the pages this run leaves out
+        let every_page = self.imports_all_pages()
+            && self.page_title_filter.is_none()
+            && self.since.is_none()
+            && unchanged_pages == 0;
+        // Neither can the deck of a page only one block is imported from, nor
+        // a deck that might only get part of its cards back under the limit
+        self.clear_deck
+            && self.block_url.is_none()
+            && self.card_limit.is_none()
+            && (own_decks || every_page)
+    }
+
+    /// Whether every page shared with the integration is imported, rather
+    /// than some pages or a single block
+    pub fn imports_all_pages(&self) -> bool {
+        self.page_ids.is_empty() && self.block_url.is_none() && self.input_path.is_none()
+    }
+
+    /// Whether `FRONT_TEMPLATE` or `BACK_TEMPLATE` has the placeholder `{name}`
+    pub fn uses_template_placeholder(&self, name: &str) -> bool {
+        let placeholder = format!("{{{}}}", name);
+        [&self.front_template, &self.back_template]
+            .into_iter()
+            .flatten()
+            .any(|template| template.contains(&placeholder))
+    }
+
+    /// Whether cards are sent to Anki-Connect, i.e. this is neither a dry run nor an export
+    pub fn uses_anki_connect(&self) -> bool {
+        !self.dry_run && !self.exports()
+    }
+
+    /// Whether cards are written to an .apkg or CSV file
+    pub fn exports(&self) -> bool {
+        self.export_path.is_some() || self.export_csv_path.is_some()
+    }
+
+    /// The CSV export delimiter, with `tab` (or `\t`) meaning a tab
+    pub fn csv_delimiter_char(&self) -> Result<char> {
+        let mut chars = self.csv_delimiter.chars();
+        match (chars.next(), chars.next()) {
+            _ if self.csv_delimiter.eq_ignore_ascii_case("tab") || self.csv_delimiter == "\\t" => Ok('\t'),
+            (Some(delimiter), None) if delimiter != '"' && delimiter != '\n' => Ok(delimiter),
+            _ => Err(Error::Config(format!("Invalid CSV_DELIMITER value: {} (expected a single character or tab)", self.csv_delimiter))),
+        }
+    }
+
+    /// Parse `field_map` into (Notion property, Anki field) pairs, in order
+    pub fn field_mappings(&self) -> Result<Vec<(String, String)>> {
+        split_list(&self.field_map)
+            .iter()
+            .map(|entry| match entry.split_once(':') {
+                Some((property, field)) if !property.trim().is_empty() && !field.trim().is_empty() => {
+                    Ok((property.trim().to_string(), field.trim().to_string()))
+                },
+                _ => Err(Error::Config(format!("Invalid FIELD_MAP entry: {} (expected Property:Field)", entry))),
+            })
+            .collect()
+    }
+
+    /// Validate required parameters
+    pub fn validate(&self) -> Result<()> {
+        // Blocks read from a file need nothing from Notion
+        if self.input_path.is_some() {
+            if self.database_id.is_some() || !self.page_ids.is_empty() || self.block_url.is_some() {
+                return Err(Error::Config("BLOCKS_INPUT can't be combined with NOTION_DATABASE_ID, NOTION_PAGE_IDS or NOTION_BLOCK_URL".to_string()));
+            }
+        } else if self.notion_api_key.is_empty() {
+            return Err(Error::MissingParameter("NOTION_API_KEY is not set; pass --notion-api-key, add it to your config file, export it in your environment, or store it with `notion2anki store-token` and set KEYCHAIN=true".to_string()));
+        } else if !self.notion_api_key.starts_with("secret_") && !self.notion_api_key.starts_with("ntn_") {
+            // Internal integration tokens start with secret_ (older) or ntn_
+            return Err(Error::Config("NOTION_API_KEY doesn't look like a Notion integration token (expected it to start with secret_ or ntn_)".to_string()));
+        }
+        if self.anki_connect_url.is_empty() {
+            return Err(Error::MissingParameter("ANKI_CONNECT_URL is empty; pass --anki-connect-url or set it to your Anki-Connect address (default: http://localhost:8765)".to_string()));
+        }
+        if !self.deck_per_page && self.deck_name.is_none() {
+            return Err(Error::MissingParameter("ANKI_DECK_NAME (required when DECK_PER_PAGE is false)".to_string()));
+        }
+        if let Some(page_id) = self.page_ids.iter().find(|id| normalize_page_id(id).is_none()) {
+            return Err(Error::Config(format!("Invalid Notion page ID: {} (expected a 32-character hex UUID)", page_id)));
+        }
+        if let Some(block_url) = &self.block_url {
+            if parse_block_url(block_url).is_none() {
+                return Err(Error::Config(format!(
+                    "Invalid Notion block link: {} (expected a page link ending in #<block id>, from \"Copy link to block\")",
+                    block_url
+                )));
+            }
+            if self.database_id.is_some() || !self.page_ids.is_empty() {
+                return Err(Error::Config("NOTION_BLOCK_URL can't be combined with NOTION_DATABASE_ID or NOTION_PAGE_IDS".to_string()));
+            }
+        }
+        if let Some(since) = &self.since {
+            if since_timestamp(since).is_none() {
+                return Err(Error::Config(format!(
+                    "Invalid EDITED_SINCE date: {} (expected a date like 2024-01-01 or an RFC 3339 time like 2024-01-01T09:00:00Z)",
+                    since
+                )));
+            }
+            if self.database_id.is_some() {
+                return Err(Error::Config("EDITED_SINCE only applies to pages and can't be combined with NOTION_DATABASE_ID".to_string()));
+            }
+        }
+        if let Some(database_id) = self.database_id.as_deref().filter(|id| normalize_page_id(id).is_none()) {
+            return Err(Error::Config(format!("Invalid Notion database ID: {} (expected a 32-character hex UUID)", database_id)));
+        }
+        if self.notion_api_version.trim().is_empty() {
+            return Err(Error::Config("NOTION_API_VERSION must not be empty".to_string()));
+        }
+        if self.question_markers.is_empty() || self.answer_markers.is_empty() {
+            return Err(Error::Config("QUESTION_MARKERS and ANSWER_MARKERS need at least one marker each".to_string()));
+        }
+        if self.deck_separator.is_empty() {
+            return Err(Error::Config("DECK_SEPARATOR must not be empty".to_string()));
+        }
+        if self.clear_deck && self.update_mode {
+            return Err(Error::Config("CLEAR_DECK can't be combined with UPDATE_MODE, which keeps the notes".to_string()));
+        }
+        if self.inline_delimiter.trim().is_empty() {
+            return Err(Error::Config("INLINE_DELIMITER must not be empty".to_string()));
+        }
+        if self.front_field.trim().is_empty() || self.back_field.trim().is_empty() || self.front_field == self.back_field {
+            return Err(Error::Config("ANKI_FRONT_FIELD and ANKI_BACK_FIELD must be two different field names".to_string()));
+        }
+        if self.http_timeout_secs == 0 {
+            return Err(Error::Config("HTTP_TIMEOUT_SECS must be at least 1".to_string()));
+        }
+        if self.tts_enabled && self.tts_url.is_none() {
+            return Err(Error::Config("TTS_ENABLED needs TTS_URL to be set".to_string()));
+        }
+        if self.equation_mode == EquationMode::Image && self.equation_render_url.is_none() {
+            return Err(Error::Config("EQUATION_MODE=image needs EQUATION_RENDER_URL to be set".to_string()));
+        }
+        if !self.notion_rate_limit.is_finite() || self.notion_rate_limit < 0.0 {
+            return Err(Error::Config("NOTION_RATE_LIMIT must be a number of requests per second, or 0".to_string()));
+        }
+        if self.search_objects.includes_databases() && (self.database_id.is_some() || !self.imports_all_pages()) {
+            return Err(Error::Config("SEARCH_OBJECTS can't include databases with NOTION_DATABASE_ID, NOTION_PAGE_IDS, NOTION_BLOCK_URL or BLOCKS_INPUT".to_string()));
+        }
+        if self.max_answer_chars == Some(0) {
+            return Err(Error::Config("MAX_ANSWER_CHARS must be at least 1".to_string()));
+        }
+        if self.card_limit == Some(0) {
+            return Err(Error::Config("CARD_LIMIT must be at least 1".to_string()));
+        }
+        if self.concurrency == 0 {
+            return Err(Error::Config("CONCURRENCY must be at least 1".to_string()));
+        }
+        self.field_mappings()?;
+        self.csv_delimiter_char()?;
+        Ok(())
+    }
+
+    /// Print usage help
+    pub fn print_usage() {
+        println!("Usage:");
+        println!("  notion2anki [OPTIONS]");
+        println!("  notion2anki doctor [OPTIONS]");
+        println!("  notion2anki store-token [OPTIONS]");
+        println!();
+        println!("Commands:");
+        println!("  doctor                        Check the setup without importing or changing anything");
+        println!("  store-token                   Store the Notion API key in the OS keychain, see KEYCHAIN");
+        println!();
+        println!("Options:");
+        println!("  -c, --config <FILE>           Specify configuration file path (JSON or TOML format)");
+        println!("  --notion-api-key <KEY>        Notion API key");
+        println!("  --anki-connect-url <URL>      Anki-Connect URL");
+        println!("  --debug <true|false>          Enable or disable debug mode");
+        println!("  --max-depth <N>               Maximum depth when fetching nested child blocks (default: 5)");
+        println!("  --deck <NAME>                 Anki deck to import into (parent deck with DECK_PER_PAGE)");
+        println!("  --tags-property <NAME>        Database multi-select property that tags the notes of its rows");
+        println!("  --card-format <FORMAT>        Flashcard layout: code, toggle, heading, cloze or inline");
+        println!("  --page-id <ID>                Import only this page (repeatable or comma-separated)");
+        println!("  --block-url <URL>             Import only the block this Notion link points to");
+        println!("  --recurse-subpages            Also import subpages, into subdecks of their parent page");
+        println!("  --strict                      Exit with an error when some blocks could not be converted");
+        println!("  --input <FILE>                Parse blocks dumped with --dump instead of fetching from Notion");
+        println!("  --dump <DIR>                  Write every page and its blocks to DIR as JSON, named by page id");
+        println!("  --update                      Update existing notes instead of skipping them as duplicates");
+        println!("  --clear-deck                  Delete all notes in the target decks before importing");
+        println!("  --dry-run                     Print cards instead of importing them into Anki");
+        println!("  --incremental                 Only import pages edited since the last run");
+        println!("  --prune                       Delete notes whose card was removed from Notion");
+        println!("  --export <PATH>               Write an .apkg file instead of importing through Anki-Connect");
+        println!("  --export-csv <PATH>           Write a CSV/TSV file instead of importing through Anki-Connect");
+        println!("  --csv-delimiter <CHAR>        CSV export column delimiter, a single character or tab (default: ,)");
+        println!("  --fail-fast                   Stop at the first card that fails to import");
+        println!("  --limit <N>                   Stop after adding N cards in total");
+        println!("  --since <DATE>                Only import pages edited on or after DATE, e.g. 2024-01-01");
+        println!("  --no-cache                    Fetch every page instead of using the block cache");
+        println!("  --no-progress                 Don't draw the progress bar");
+        println!("  --create-model                Create a basic note type when Anki has none");
+        println!("  --emit-ndjson <PATH>          Write a JSON line per imported card to PATH");
+        println!("  --report <FORMAT>             Summary format: text or json (default: text)");
+        println!("  -h, --help                    Show help information");
+        println!();
+        println!("Configuration Priority (per setting):");
+        println!("  1. Command line arguments (highest)");
+        println!("  2. Configuration file - Used when --config is specified");
+        println!("  3. Environment variables, including a .env file");
+        println!("  4. notion2anki.toml in the working directory or ~/.config/notion2anki/,");
+        println!("     used when --config is not specified (lowest)");
+        println!();
+        println!("Environment Variables:");
+        println!("  NOTION_API_KEY          Notion API key");
+        println!("  ANKI_CONNECT_URL        Anki-Connect URL (default: http://localhost:8765)");
+        println!("  DEBUG_MODE              Enable debug mode (true/false)");
+        println!("  MAX_BLOCK_DEPTH         Maximum nested block depth (default: 5)");
+        println!("  CARD_FORMAT             Flashcard layout: code, toggle, heading, cloze or inline (default: code)");
+        println!("  TEXT_FORMAT             Rich text output: plain or html (default: plain)");
+        println!("  ANKI_MODEL_NAME         Model for basic cards (default: Basic, or 基本/Basique when Anki has no Basic)");
+        println!("  ANKI_CLOZE_MODEL_NAME   Model for cloze cards (default: Cloze, or 填空题/Texte à trous)");
+        println!("  ANKI_DECK_NAME          Default deck, or parent deck when DECK_PER_PAGE is true");
+        println!("  DECK_PER_PAGE           One deck per Notion page, named after its title (default: true)");
+        println!("  UPDATE_MODE             Update existing notes instead of skipping duplicates (true/false)");
+        println!("  CLEAR_DECK              Delete all notes in the target decks first (true/false)");
+        println!("  REVERSE_CARDS           Also create reversed (Back→Front) cards (true/false)");
+        println!("  NOTION_IMPORT_TAG       Tag added to every imported note (default: notion-import)");
+        println!("  TAG_PAGE_TITLE          Tag notes with their page title (default: true)");
+        println!("  DRY_RUN                 Print cards instead of importing them (true/false)");
+        println!("  NOTION_PAGE_IDS         Comma-separated page IDs to import instead of all pages");
+        println!("  NOTION_DATABASE_ID      Import the rows of this database as cards instead of pages");
+        println!("  NOTION_QUESTION_PROPERTY Database property used as the Front (default: Question)");
+        println!("  NOTION_ANSWER_PROPERTY  Database property used as the Back (default: Answer)");
+        println!("  NOTION_TAGS_PROPERTY    Database multi-select property that tags the notes of its rows");
+        println!("  FIELD_MAP               Database property to note field map, e.g. Question:Front,Answer:Back");
+        println!("  CONCURRENCY             Pages fetched and parsed in parallel (default: 3)");
+        println!("  PRESERVE_ORDER          Add cards in the order of the pages, not as fetches finish (true/false)");
+        println!("  INCREMENTAL_SYNC        Only import pages edited since the last run (true/false)");
+        println!("  STATE_FILE              Sync state file (default: .notion2anki.json)");
+        println!("  PRUNE                   Delete notes whose card was removed from Notion (true/false)");
+        println!("  ANKI_CONNECT_API_KEY    API key, if Anki-Connect is configured to require one");
+        println!("  EXPORT_PATH             Write an .apkg file instead of importing through Anki-Connect");
+        println!("  EXPORT_CSV_PATH         Write a CSV/TSV file instead of importing through Anki-Connect");
+        println!("  CSV_DELIMITER           CSV export column delimiter, a single character or tab (default: ,)");
+        println!("  NOTION_API_VERSION      Notion API version to request (default: 2022-06-28)");
+        println!("  NOTION_API_URL          Base URL of the Notion API (default: https://api.notion.com/v1)");
+        println!("  CARD_SEPARATOR          Line ending a code-block card, empty to disable (default: ---)");
+        println!("  QUESTION_MARKERS        Comma-separated question markers (default: 问题,Question)");
+        println!("  ANSWER_MARKERS          Comma-separated answer markers (default: 答案,Answer,回答)");
+        println!("  REPORT_FORMAT           Summary format: text or json (default: text)");
+        println!("  FAIL_FAST               Abort on the first card that fails to import (true/false)");
+        println!("  HTTP_TIMEOUT_SECS       Timeout of every HTTP request in seconds (default: 30)");
+        println!("  ANKI_FRONT_FIELD        Note type field for the question (default: Front)");
+        println!("  ANKI_BACK_FIELD         Note type field for the answer (default: Back)");
+        println!("  ANKI_SOURCE_FIELD       Note type field for a link to the Notion page, empty to disable (default: Source)");
+        println!("  CARD_LIMIT              Stop after adding this many cards in total");
+        println!("  PAGE_TITLE_FILTER       Import only pages whose title matches this text");
+        println!("  PAGE_TITLE_MATCH        How PAGE_TITLE_FILTER matches: prefix or contains (default: prefix)");
+        println!("  SHOW_PROGRESS           Show a progress bar in a terminal (true/false, default: true)");
+        println!("  TTS_ENABLED             Add spoken audio of the Front to basic cards (true/false)");
+        println!("  TTS_URL                 Text-to-speech service URL used by TTS_ENABLED");
+        println!("  BLOCK_CACHE             Cache fetched page blocks on disk (true/false)");
+        println!("  CACHE_DIR               Block cache directory (default: .notion2anki-cache)");
+        println!("  NOTION_BLOCK_URL        Link to the one block (toggle, section, ...) to import");
+        println!("  NOTION_RATE_LIMIT       Notion requests per second, 0 for no limit (default: 3)");
+        println!("  FRONT_TEMPLATE          Front of basic cards, e.g. [{{title}}] {{front}}");
+        println!("  BACK_TEMPLATE           Back of basic cards, e.g. {{back}}<br>({{title}})");
+        println!("  CREATE_MODEL            Create a Front/Back note type when Anki has no Basic one (true/false)");
+        println!("  EDITED_SINCE            Only import pages edited on or after this date, e.g. 2024-01-01");
+        println!("  DECK_HEADINGS           Top-level H1 headings set the deck of the cards below them (true/false)");
+        println!("  ANKI_CONNECT_RETRIES    Retries when Anki-Connect refuses the connection (default: 3)");
+        println!("  BLOCKS_INPUT            JSON file of dumped blocks to import instead of Notion pages");
+        println!("  BLOCKS_DUMP_DIR         Directory to write the fetched blocks of every page to");
+        println!("  RECURSE_SUBPAGES        Also import subpages, into subdecks of their parent page (true/false)");
+        println!("  DECK_SEPARATOR          Joins parent and child deck names (default: ::)");
+        println!("  DECK_HIERARCHY          Name page decks after their parent pages, e.g. Root::Biology::Cells (true/false)");
+        println!("  EQUATION_MODE           How equations are shown: mathjax or image (default: mathjax)");
+        println!("  EQUATION_RENDER_URL     LaTeX-to-image service URL used by EQUATION_MODE=image");
+        println!("  STRICT                  Exit with an error when some blocks could not be converted (true/false)");
+        println!("  KEYCHAIN                Read NOTION_API_KEY from the OS keychain, stored with store-token (true/false)");
+        println!("  KEYCHAIN_SERVICE        Service name of the keychain entry (default: notion2anki)");
+        println!("  KEYCHAIN_ACCOUNT        Account name of the keychain entry (default: NOTION_API_KEY)");
+        println!("  DEDUPE_CARDS            Drop repeated cards of a deck: front-back, front or off (default: front-back)");
+        println!("  MAX_ANSWER_CHARS        Longest answer a card may have, in characters; see LONG_ANSWER");
+        println!("  LONG_ANSWER             Cards over MAX_ANSWER_CHARS: skip or truncate (default: skip)");
+        println!("  QUOTE_AS_HINT           Show quote blocks as hints that open when clicked (true/false)");
+        println!("  SEARCH_OBJECTS          What the search imports: pages, databases or both (default: pages)");
+        println!("  DECK_OPTIONS            Options group that new decks get, created from Default if missing");
+        println!("  BLOCK_COMMENTS          Add the comments on the block of a card to its answer (true/false)");
+        println!("  EMIT_NDJSON             File to write a JSON line per imported card to");
+        println!("  INLINE_DELIMITER        Splits question and answer with CARD_FORMAT=inline (default: ::)");
+        println!();
+        println!("Configuration File Example (config.toml):");
+        println!("  notion_api_key = \"your_notion_api_key\"");
+        println!("  anki_connect_url = \"http://localhost:8765\"");
+        println!("  debug_mode = false");
+        println!("  max_block_depth = 5");
+        println!("  card_format = \"code\"");
+        println!("  text_format = \"plain\"");
+        println!("  deck_per_page = true");
+        println!();
+        println!("Configuration File Example (config.json):");
+        println!("  {{");
+        println!("    \"notion_api_key\": \"your_notion_api_key\",");
+        println!("    \"anki_connect_url\": \"http://localhost:8765\",");
+        println!("    \"debug_mode\": false,");
+        println!("    \"max_block_depth\": 5,");
+        println!("    \"card_format\": \"code\",");
+        println!("    \"text_format\": \"plain\",");
+        println!("    \"deck_per_page\": true");
+        println!("  }}");
+    }
+}
+
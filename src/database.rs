@@ -0,0 +1,98 @@
+use serde_json::Value;
+use tracing::debug;
+
+use crate::config::{Config, TextFormat};
+use crate::markdown::{escape_html, field_rich_text};
+use crate::notion::NotionPage;
+use crate::parser::Flashcard;
+
+/// Turn database rows into flashcards, reading the Front and Back from the
+/// configured question and answer properties. Rows without a question are skipped.
+/// With field mappings, each mapped property fills its Anki field instead and
+/// the first mapped field counts as the question. The options of the
+/// `tags_property` multi-select become the tags of the row's card.
+pub fn database_row_flashcards(rows: &[NotionPage], mappings: &[(String, String)], config: &Config) -> Vec<Flashcard> {
+    let mut flashcards = Vec::new();
+    for row in rows {
+        let tags = config.tags_property.as_ref().map(|property| row_tags(&row.properties[property])).unwrap_or_default();
+        if !mappings.is_empty() {
+            let fields: Vec<(String, String)> = mappings
+                .iter()
+                .map(|(property, field)| {
+                    let text = property_text(&row.properties[property], config.text_format);
+                    (field.clone(), text.trim().to_string())
+                })
+                .collect();
+            // Anki refuses notes whose first field is empty
+            if fields[0].1.is_empty() {
+                debug!("Skipping database row {} without \"{}\"", row.id, mappings[0].0);
+                continue;
+            }
+            flashcards.push(Flashcard {
+                question: fields[0].1.clone(),
+                answer: fields.get(1).map(|(_, text)| text.clone()).unwrap_or_default(),
+                fields,
+                audio: None,
+                source_block: Some(row.id.clone()),
+                source_question: None,
+                tags: tags.clone(),
+            });
+            continue;
+        }
+        
+        let question = property_text(&row.properties[&config.question_property], config.text_format);
+        let answer = property_text(&row.properties[&config.answer_property], config.text_format);
+        if question.trim().is_empty() {
+            debug!("Skipping database row {} without \"{}\"", row.id, config.question_property);
+            continue;
+        }
+        flashcards.push(Flashcard {
+            question: question.trim().to_string(),
+            answer: answer.trim().to_string(),
+            fields: Vec::new(),
+            audio: None,
+            source_block: Some(row.id.clone()),
+            source_question: None,
+            tags,
+        });
+    }
+    flashcards
+}
+
+/// Anki tags from the options of a multi-select property. Tags can't contain
+/// spaces, so those become dashes.
+fn row_tags(property: &Value) -> Vec<String> {
+    property["multi_select"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|option| option["name"].as_str())
+        .map(|name| name.split_whitespace().collect::<Vec<_>>().join("-"))
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Text of a database property value as it goes into an Anki field. Title and
+/// rich-text properties keep their formatting according to `format`, other
+/// values are HTML-escaped; missing properties are empty.
+pub fn property_text(property: &Value, format: TextFormat) -> String {
+    let kind = property["type"].as_str().unwrap_or_default();
+    let value = &property[kind];
+    let text = match kind {
+        "title" | "rich_text" => return field_rich_text(value, format).unwrap_or_default(),
+        "select" | "status" => value["name"].as_str().unwrap_or_default().to_string(),
+        "multi_select" => value
+            .as_array()
+            .map(|options| {
+                options.iter()
+                    .filter_map(|option| option["name"].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_default(),
+        "number" => value.as_f64().map(|n| n.to_string()).unwrap_or_default(),
+        "url" | "email" | "phone_number" => value.as_str().unwrap_or_default().to_string(),
+        _ => String::new(),
+    };
+    escape_html(&text)
+}
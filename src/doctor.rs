@@ -0,0 +1,99 @@
+use std::io::{self, IsTerminal};
+
+use crate::anki::{check_anki_connect, deck_names, find_models};
+use crate::config::Config;
+use crate::notion::fetch_bot_name;
+
+/// Result of one `doctor` check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    /// Works, but maybe not the way it was meant to
+    Warning,
+    Failed,
+}
+
+/// Prints the report, counting the failed checks
+struct Report {
+    color: bool,
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, status: Status, message: &str) {
+        let (mark, color) = match status {
+            Status::Ok => ("✓", "32"),
+            Status::Warning => ("!", "33"),
+            Status::Failed => ("✗", "31"),
+        };
+        if status == Status::Failed {
+            self.failed += 1;
+        }
+        if self.color {
+            println!("\x1b[{}m{}\x1b[0m {}", color, mark, message);
+        } else {
+            println!("{} {}", mark, message);
+        }
+    }
+}
+
+/// Check the setup without changing anything in Notion or Anki: the
+/// configuration, the Notion API key, Anki-Connect, the deck and the note
+/// types. Prints a line per check and returns whether none failed.
+pub async fn run_doctor(config: &Config) -> bool {
+    let mut report = Report { color: io::stdout().is_terminal(), failed: 0 };
+    
+    match config.validate() {
+        Ok(()) => report.check(Status::Ok, "Configuration is valid"),
+        Err(error) => report.check(Status::Failed, &format!("Configuration: {}", error)),
+    }
+    
+    // A missing key fails the validation, unless blocks are read from a file
+    if config.notion_api_key.is_empty() {
+        if config.input_path.is_some() {
+            report.check(Status::Warning, "NOTION_API_KEY is not set, so only --input can be used");
+        }
+    } else {
+        match fetch_bot_name(config).await {
+            Ok(name) => report.check(Status::Ok, &format!("Notion accepts the API key of the integration \"{}\"", name)),
+            Err(error) => report.check(Status::Failed, &format!("Notion API key: {}", error)),
+        }
+    }
+    
+    match check_anki_connect(config).await {
+        Ok(version) => report.check(Status::Ok, &format!("Anki-Connect answers at {} (API version {})", config.anki_connect_url, version)),
+        Err(error) => {
+            report.check(Status::Failed, &error.to_string());
+            report.check(Status::Warning, "Skipped the deck and note type checks, which need Anki-Connect");
+            return report.failed == 0;
+        },
+    }
+    
+    match &config.deck_name {
+        None => report.check(Status::Ok, "Decks are named after the pages"),
+        Some(deck_name) => match deck_names(config).await {
+            Ok(decks) if decks.contains(deck_name) => report.check(Status::Ok, &format!("Deck \"{}\" exists", deck_name)),
+            Ok(_) => report.check(Status::Warning, &format!("Anki has no deck \"{}\" yet; the first import creates it", deck_name)),
+            Err(error) => report.check(Status::Failed, &format!("Failed to list the decks: {}", error)),
+        },
+    }
+    
+    match find_models(config).await {
+        Ok((basic, cloze)) => {
+            match basic {
+                Some(basic) => report.check(Status::Ok, &format!("Basic cards use the note type \"{}\"", basic)),
+                None if config.create_model => {
+                    report.check(Status::Warning, "Anki has no basic note type; the first import creates one (--create-model)");
+                },
+                None => report.check(Status::Failed, "Anki has no basic note type; set ANKI_MODEL_NAME or pass --create-model"),
+            }
+            match cloze {
+                Some(cloze) => report.check(Status::Ok, &format!("Cloze cards use the note type \"{}\"", cloze)),
+                None => report.check(Status::Warning, "Anki has no cloze note type, so cloze cards can't be added; set ANKI_CLOZE_MODEL_NAME"),
+            }
+        },
+        Err(error) => report.check(Status::Failed, &format!("Failed to list the note types: {}", error)),
+    }
+    
+    report.failed == 0
+}
@@ -0,0 +1,33 @@
+/// Errors that can occur while importing
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Network or HTTP failure talking to Notion, Anki-Connect or an image host
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Anki-Connect rejected a request
+    #[error("Anki-Connect error: {0}")]
+    AnkiConnect(String),
+    /// Notion rejected the API key
+    #[error("{0}")]
+    Auth(String),
+    /// A Notion page or block doesn't exist or isn't shared with the integration
+    #[error("Notion object not found: {0}")]
+    NotFound(String),
+    /// Notion rejected a request
+    #[error("Notion API error: {0}")]
+    Notion(String),
+    /// A Notion or Anki-Connect response could not be parsed
+    #[error("Failed to parse response: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// A required setting was not provided by any configuration source
+    #[error("Missing required parameter: {0}")]
+    MissingParameter(String),
+    /// Writing an export file failed
+    #[error("Failed to export flashcards: {0}")]
+    Export(String),
+    /// A setting was provided but is invalid
+    #[error("{0}")]
+    Config(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,145 @@
+use genanki_rs::{basic_model, cloze_model, Deck, Note, Package};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{LineWriter, Write};
+
+use crate::anki::cloze_text;
+use crate::error::{Error, Result};
+use crate::markdown::field_html;
+use crate::state::{card_key, stable_hash};
+use crate::DeckCards;
+
+/// Write all cards to an Anki package (.apkg) that can be imported without
+/// Anki-Connect. Basic cards use genanki's Basic model and cloze cards its
+/// Cloze model; cards with mapped fields keep only their first two fields.
+pub fn write_apkg(path: &str, decks: &[DeckCards]) -> Result<()> {
+    // Pages sharing a deck end up in the same package deck
+    let mut package_decks: BTreeMap<&str, Deck> = BTreeMap::new();
+    for cards in decks {
+        let deck = package_decks
+            .entry(cards.deck_name.as_str())
+            .or_insert_with(|| Deck::new(package_deck_id(&cards.deck_name), &cards.deck_name, ""));
+        
+        for flashcard in &cards.flashcards {
+            let note = if flashcard.is_cloze() && flashcard.fields.is_empty() {
+                Note::new(cloze_model(), vec![cloze_text(flashcard).as_str()])
+            } else {
+                Note::new(basic_model(), vec![field_html(&flashcard.question).as_str(), field_html(&flashcard.answer).as_str()])
+            }
+            .map_err(export_error)?
+            // A stable guid lets Anki update the notes when the package is imported again
+            .guid(format!("{:016x}", stable_hash(&card_key(&cards.source_id, flashcard))))
+            .tags(cards.tags.iter().chain(&flashcard.tags));
+            deck.add_note(note);
+        }
+    }
+    
+    let mut package = Package::new(package_decks.into_values().collect(), vec![]).map_err(export_error)?;
+    package.write_to_file(path).map_err(export_error)
+}
+
+/// Deck ids must stay the same across exports so re-imports merge into one deck
+fn package_deck_id(deck_name: &str) -> i64 {
+    (stable_hash(deck_name) >> 1) as i64
+}
+
+fn export_error(error: genanki_rs::Error) -> Error {
+    Error::Export(error.to_string())
+}
+
+/// Write all cards to a CSV file for Anki's File → Import, one row per card with
+/// Front, Back, Deck, Tags and note type columns. The header lines tell Anki the
+/// delimiter and which columns hold the deck, tags and note type.
+pub fn write_csv(path: &str, decks: &[DeckCards], delimiter: char) -> Result<()> {
+    let separator = match delimiter {
+        ',' => "Comma".to_string(),
+        ';' => "Semicolon".to_string(),
+        '\t' => "Tab".to_string(),
+        '|' => "Pipe".to_string(),
+        other => other.to_string(),
+    };
+    let mut csv = format!("#separator:{}\n#html:true\n#deck column:3\n#tags column:4\n#notetype column:5\n", separator);
+    
+    for cards in decks {
+        for flashcard in &cards.flashcards {
+            let tags = cards.tags.iter().chain(&flashcard.tags).map(String::as_str).collect::<Vec<_>>().join(" ");
+            // Cloze notes take the text as their first field and the answer as Back Extra
+            let notetype = if flashcard.is_cloze() && flashcard.fields.is_empty() { "Cloze" } else { "Basic" };
+            // The file is imported as HTML, where plain line breaks would collapse
+            let (question, answer) = (field_html(&flashcard.question), field_html(&flashcard.answer));
+            let row = [question.as_str(), answer.as_str(), cards.deck_name.as_str(), tags.as_str(), notetype];
+            let row: Vec<String> = row.iter().map(|value| csv_field(value, delimiter)).collect();
+            csv.push_str(&row.join(&delimiter.to_string()));
+            csv.push('\n');
+        }
+    }
+    
+    fs::write(path, csv).map_err(|e| Error::Export(format!("Failed to write {}: {}", path, e)))
+}
+
+/// Quote a field that contains the delimiter, quotes or line breaks, doubling its quotes
+fn csv_field(value: &str, delimiter: char) -> String {
+    if value.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// What the import did with a card, as recorded in the `--emit-ndjson` log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardStatus {
+    /// Added to Anki, or would be in a dry run
+    Added,
+    /// An existing note was updated in place
+    Updated,
+    /// Anki already has the card
+    Skipped,
+    /// Anki-Connect didn't take the card
+    Failed,
+}
+
+/// One line of the `--emit-ndjson` log
+#[derive(Debug, Serialize)]
+pub struct CardRecord<'a> {
+    pub page_id: &'a str,
+    pub deck: &'a str,
+    pub front: &'a str,
+    pub back: &'a str,
+    /// The note the card is in, when Anki-Connect told
+    pub anki_note_id: Option<u64>,
+    pub status: CardStatus,
+    /// Why a failed card failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+}
+
+/// Writes a JSON object per line for every card the import gets to, as it
+/// gets to it, so a long run can be followed and nothing is held in memory.
+/// Without a path nothing is written.
+#[derive(Debug, Default)]
+pub struct CardLog {
+    path: String,
+    writer: Option<LineWriter<File>>,
+}
+
+impl CardLog {
+    /// Start the log at `path`, replacing a file that is there
+    pub fn create(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let file = File::create(path).map_err(|e| Error::Export(format!("Failed to create {}: {}", path, e)))?;
+        Ok(Self { path: path.to_string(), writer: Some(LineWriter::new(file)) })
+    }
+    
+    pub fn record(&mut self, record: &CardRecord) -> Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(record)?;
+        writeln!(writer, "{}", line).map_err(|e| Error::Export(format!("Failed to write {}: {}", self.path, e)))
+    }
+}
@@ -0,0 +1,58 @@
+use std::process::{Command, Stdio};
+
+use crate::error::{Error, Result};
+
+/// Label of the keychain entry written by `store_token`
+const ENTRY_LABEL: &str = "notion2anki Notion API key";
+
+/// The Notion API key stored under `service` and `account` in the OS keychain:
+/// the login keychain on macOS, the Secret Service (GNOME Keyring, KWallet)
+/// elsewhere. `None` when there is no such entry or no keychain to ask, which
+/// is always the case on Windows.
+pub fn read_token(service: &str, account: &str) -> Option<String> {
+    if cfg!(windows) {
+        return None;
+    }
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args(["find-generic-password", "-s", service, "-a", account, "-w"]);
+        command
+    } else {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", service, "account", account]);
+        command
+    };
+    let output = command.stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    Some(token).filter(|token| !token.is_empty())
+}
+
+/// Ask for the Notion API key on the terminal and store it in the OS keychain,
+/// replacing the one stored before. The keychain tool does the asking, so the
+/// key isn't echoed and never shows up in a command line. Windows has no
+/// keychain tool to run, so there this is an error.
+pub fn store_token(service: &str, account: &str) -> Result<()> {
+    if cfg!(windows) {
+        return Err(Error::Config("There is no supported keychain on Windows; set NOTION_API_KEY instead".to_string()));
+    }
+    let (program, mut command) = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        // -w has to come last to be asked for
+        command.args(["add-generic-password", "-U", "-l", ENTRY_LABEL, "-s", service, "-a", account, "-w"]);
+        ("security", command)
+    } else {
+        let mut command = Command::new("secret-tool");
+        command.args(["store", "--label", ENTRY_LABEL, "service", service, "account", account]);
+        ("secret-tool", command)
+    };
+    let status = command
+        .status()
+        .map_err(|e| Error::Config(format!("Failed to run {} to reach the keychain: {}", program, e)))?;
+    if !status.success() {
+        return Err(Error::Config(format!("{} couldn't store the key in the keychain ({})", program, status)));
+    }
+    Ok(())
+}
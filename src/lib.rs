@@ -0,0 +1,1059 @@
+//! Import flashcards from Notion pages into Anki through Anki-Connect
+
+pub mod anki;
+pub mod cache;
+pub mod config;
+pub mod database;
+pub mod doctor;
+pub mod error;
+pub mod export;
+pub mod keychain;
+pub mod markdown;
+pub mod media;
+pub mod mentions;
+pub mod notion;
+pub mod parser;
+pub mod progress;
+pub mod state;
+
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+pub use config::{Args, CardDedupe, CardFormat, Command, Config, EquationMode, LongAnswer, ReportFormat, SearchObjects, TextFormat, TitleMatch};
+pub use error::{Error, Result};
+pub use markdown::{convert_blocks_to_markdown, extract_rich_text, field_rich_text};
+pub use notion::{fetch_all_pages, fetch_page_content, fetch_pages_by_id, NotionPage};
+pub use parser::{parse_flashcards_from_markdown, Flashcard};
+
+use anki::{
+    add_note_tag, add_notes_to_anki, build_note, can_add_notes, check_anki_connect, clear_deck,
+    create_deck_if_not_exists, deck_name_for_page, deck_names, delete_notes, find_keyed_notes, find_note_by_front,
+    check_model_fields, model_field_names, page_tags, resolve_models, set_deck_options, update_note_fields,
+};
+use cache::{load_cached_blocks, store_cached_blocks};
+use database::database_row_flashcards;
+use export::{write_apkg, write_csv, CardLog, CardRecord, CardStatus};
+use media::{add_front_audio, store_page_equations, store_page_images, store_page_media, PageMedia};
+use mentions::{resolve_page_mentions, MentionTitles};
+use markdown::{convert_blocks_with_sources, skipped_block_types, split_deck_sections};
+use notion::{
+    child_pages, extract_page_title, fetch_all_database_ids, fetch_block_comments, fetch_block_subtree, page_ancestors, fetch_database_title, normalize_page_id, notion_url, parse_block_url,
+    query_database, title_matches,
+};
+use parser::{add_reversed_cards, append_comments, apply_card_templates, parse_flashcards_with_sources, remove_repeated_cards};
+use progress::Progress;
+use state::{card_key, database_source_id, edited_since, is_page_source, note_key_tag, now_timestamp, since_timestamp, SyncState};
+
+/// Counts reported at the end of a run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    /// Notion pages processed
+    pub pages: usize,
+    /// Flashcards parsed from all pages
+    pub cards: usize,
+    /// Notes added to Anki, or that would be added in a dry run
+    pub added: usize,
+    /// Existing notes updated in place (update mode)
+    pub updated: usize,
+    /// Cards skipped because Anki already has them
+    pub duplicates: usize,
+    /// Cards dropped because their deck already got the same card in this run
+    pub repeated: usize,
+    /// Pages skipped by incremental sync because they weren't edited
+    pub unchanged: usize,
+    /// Pages skipped because the integration can't read them
+    pub inaccessible: usize,
+    /// Pages (or a database) in which no flashcards were found
+    pub empty_pages: usize,
+    /// Blocks left out of the cards because they have no conversion, per block type
+    pub skipped_blocks: BTreeMap<String, usize>,
+    /// Whether the run stopped early because `CARD_LIMIT` cards were added
+    pub limit_reached: bool,
+    /// Whether the run stopped early because of Ctrl-C
+    pub interrupted: bool,
+    /// Pages fetched and imported (or found empty) before the run ended
+    pub processed_pages: usize,
+    /// Notes deleted (or that would be deleted) because their card is gone
+    pub pruned: usize,
+    /// Cards that couldn't be added or updated
+    pub failed: Vec<FailedCard>,
+    /// Cards found and added per page (or database)
+    pub page_reports: Vec<PageReport>,
+}
+
+impl Summary {
+    /// Whether `--strict` fails the run: some blocks had no conversion
+    pub fn fails_strict(&self, config: &Config) -> bool {
+        config.strict && !self.skipped_blocks.is_empty()
+    }
+    
+    /// Drop the cards repeated in this run, see `remove_repeated_cards`
+    fn remove_repeated(&mut self, cards: &mut DeckCards, seen: &mut HashSet<String>, config: &Config) {
+        let repeated = remove_repeated_cards(&mut cards.flashcards, &cards.deck_name, seen, config.dedupe_cards);
+        if repeated > 0 {
+            info!("Dropped {} repeated flashcards of \"{}\"", repeated, cards.title);
+            self.repeated += repeated;
+        }
+    }
+    
+    /// Count a page's cards and add its line to the per-page report
+    fn report_page(&mut self, cards: &DeckCards, added: usize, updated: usize) {
+        self.cards += cards.flashcards.len();
+        self.page_reports.push(PageReport {
+            title: cards.title.clone(),
+            deck: cards.deck_name.clone(),
+            cards: cards.flashcards.len(),
+            added,
+            updated,
+        });
+    }
+    
+    /// How many more cards may be added before `CARD_LIMIT` is reached
+    fn remaining(&self, config: &Config) -> usize {
+        config.card_limit.map_or(usize::MAX, |limit| limit.saturating_sub(self.added))
+    }
+    
+    /// Keep only the cards still allowed by `CARD_LIMIT`, noting when some are cut off
+    fn limit_cards<'a>(&mut self, flashcards: &'a [Flashcard], config: &Config) -> &'a [Flashcard] {
+        let remaining = self.remaining(config);
+        if flashcards.len() > remaining {
+            self.limit_reached = true;
+        }
+        &flashcards[..flashcards.len().min(remaining)]
+    }
+}
+
+/// A card Anki-Connect didn't take, with the reason
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedCard {
+    pub page: String,
+    pub question: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PageReport {
+    pub title: String,
+    pub deck: String,
+    pub cards: usize,
+    pub added: usize,
+    pub updated: usize,
+}
+
+/// Cards parsed from one page (or database), ready for Anki or an export
+#[derive(Debug, Clone)]
+pub struct DeckCards {
+    /// Page id, or `database_source_id` for a database
+    pub source_id: String,
+    /// Title of the page or database
+    pub title: String,
+    /// Link to the page or database in Notion
+    pub source_url: String,
+    pub deck_name: String,
+    pub tags: Vec<String>,
+    pub flashcards: Vec<Flashcard>,
+}
+
+/// Cards of a page and its subpages, as returned by `fetch_and_parse_page`
+struct ParsedPage {
+    decks: Vec<DeckCards>,
+    /// See `skipped_block_types`
+    skipped_blocks: BTreeMap<String, usize>,
+}
+
+fn add_counts(counts: &mut BTreeMap<String, usize>, more: BTreeMap<String, usize>) {
+    for (key, count) in more {
+        *counts.entry(key).or_default() += count;
+    }
+}
+
+/// Import flashcards from every accessible Notion page into Anki, or from
+/// the rows of `NOTION_DATABASE_ID` when one is configured. With an export
+/// path the cards are written to an Anki package or CSV file instead.
+pub async fn run(config: &Config) -> Result<Summary> {
+    // Fail before fetching anything from Notion when Anki can't take the cards
+    // Basic notes only link back to Notion when their note type has the source field
+    let mut source_field = None;
+    if config.uses_anki_connect() {
+        check_anki_connect(config).await?;
+        resolve_models(config).await?;
+        // Database rows with FIELD_MAP check their own fields
+        if config.field_map.is_empty() {
+            let model_fields = check_model_fields(config).await?;
+            if let Some(field) = &config.source_field {
+                if model_fields.contains(field) {
+                    source_field = Some(field.as_str());
+                } else {
+                    debug!("The note type has no \"{}\" field, so notes won't link to Notion", field);
+                }
+            }
+        }
+    }
+    
+    // Notes are only looked up by their key tag when they are updated in place
+    let keyed_notes = if config.update_mode && config.uses_anki_connect() {
+        find_keyed_notes(config).await?
+    } else {
+        HashMap::new()
+    };
+    
+    let mut summary = Summary::default();
+    // Several pages can share a deck, so each deck is created and cleared
+    // only the first time it is seen in this run
+    let mut prepared_decks = HashSet::new();
+    let mut exported = Vec::new();
+    let mut card_log = CardLog::create(config.emit_ndjson.as_deref())?;
+    
+    // The state file holds the last sync time and the note id of every card
+    // imported before, which update mode uses to find the notes again
+    let use_state = config.incremental || config.update_mode || config.prune;
+    let keep_state = use_state && config.uses_anki_connect();
+    let mut state = if use_state {
+        SyncState::load(&config.state_file)?
+    } else {
+        SyncState::default()
+    };
+    
+    // The configured database, or every database search finds when
+    // SEARCH_OBJECTS includes them
+    let database_ids = match &config.database_id {
+        Some(database_id) => vec![database_id.clone()],
+        None if config.search_objects.includes_databases() => fetch_all_database_ids(config).await?,
+        None => Vec::new(),
+    };
+    for database_id in &database_ids {
+        let mut cards = fetch_database_cards(database_id, config).await?;
+        summary.pages += 1;
+        summary.processed_pages += 1;
+        summary.remove_repeated(&mut cards, &mut HashSet::new(), config);
+        if config.exports() {
+            cards.flashcards = summary.limit_cards(&cards.flashcards, config).to_vec();
+            println!("Collected {} flashcards for deck \"{}\"", cards.flashcards.len(), cards.deck_name);
+            summary.added += cards.flashcards.len();
+            summary.report_page(&cards, cards.flashcards.len(), 0);
+            exported.push(cards);
+        } else {
+            prepare_deck(&cards.deck_name, config.clear_deck, config, &mut prepared_decks).await?;
+            let seen_keys: HashSet<String> = import_flashcards(&cards, source_field, &keyed_notes, config, &mut state, &mut summary, &mut card_log)
+                .await?
+                .into_iter()
+                .collect();
+            if config.prune {
+                let orphans = orphaned_notes(&state, &seen_keys, |source| source == cards.source_id);
+                prune_notes(orphans, config, &mut state, &mut summary).await?;
+            }
+            println!("\nCompleted importing database \"{}\"", cards.deck_name);
+        }
+    }
+    if config.database_id.is_none() && config.search_objects.includes_pages() {
+        // Edits made while this run is in progress must count for the next one
+        let sync_started = now_timestamp();
+        
+        // Explicit page IDs skip the search for every shared page; a block
+        // link names the page holding the block
+        let block_link = config.block_url.as_deref().and_then(parse_block_url);
+        let mut pages = if let Some(input_path) = &config.input_path {
+            vec![input_page(input_path)]
+        } else if let Some((page_id, _)) = &block_link {
+            fetch_pages_by_id(std::slice::from_ref(page_id), config).await?
+        } else if config.page_ids.is_empty() {
+            let mut pages = fetch_all_pages(config).await?;
+            // Database rows are pages too, but they were imported as rows above
+            if config.search_objects.includes_databases() {
+                pages.retain(|page| page.parent_database_id.is_none());
+            }
+            pages
+        } else {
+            fetch_pages_by_id(&config.page_ids, config).await?
+        };
+        println!("Found {} pages to import", pages.len());
+        let found_pages: HashSet<String> = pages.iter().map(|page| page.id.clone()).collect();
+        // Titles of the pages found also name the decks of the pages below them
+        let ancestors = if config.deck_hierarchy {
+            page_ancestors(&pages, config).await
+        } else {
+            HashMap::new()
+        };
+        
+        // Subpages are imported through their parent, into its subdeck
+        if config.recurse_subpages {
+            pages.retain(|page| !page.parent_page_id.as_ref().is_some_and(|parent| found_pages.contains(parent)));
+        }
+        
+        // Filtered out pages are still found, so pruning leaves their notes alone
+        if let Some(filter) = &config.page_title_filter {
+            let found = pages.len();
+            pages.retain(|page| {
+                page.title.as_deref().is_some_and(|title| title_matches(title, filter, config.page_title_match))
+            });
+            if pages.len() < found {
+                println!("Skipping {} pages whose title doesn't match \"{}\"", found - pages.len(), filter);
+            }
+        }
+        
+        // Notion's edit times are rounded down to the minute, so a page edited
+        // in the minute before the given time is kept
+        if let Some(since) = config.since.as_deref().and_then(since_timestamp) {
+            let found = pages.len();
+            pages.retain(|page| edited_since(&page.last_edited_time, &since));
+            if pages.len() < found {
+                println!("Skipping {} pages not edited since {}", found - pages.len(), since);
+            }
+        }
+        
+        if config.incremental {
+            let found = pages.len();
+            pages.retain(|page| state.is_changed(&page.last_edited_time));
+            summary.unchanged = found - pages.len();
+            if summary.unchanged > 0 {
+                println!("Skipping {} pages unchanged since the last sync", summary.unchanged);
+            }
+        }
+        summary.pages += pages.len();
+        
+        let clear_decks = config.clears_decks(summary.unchanged);
+        
+        // Pages are fetched and parsed concurrently (bounded so Notion's rate
+        // limit isn't hit), then imported into Anki one at a time as they
+        // finish, or in the order they were found with PRESERVE_ORDER
+        let mut imported_pages = HashSet::new();
+        let mut seen_keys = HashSet::new();
+        // Cards kept so far, for DEDUPE_CARDS across pages sharing a deck
+        let mut run_cards = HashSet::new();
+        let mention_titles = MentionTitles::default();
+        let mut progress = Progress::new(pages.len(), config);
+        let page_fetches = stream::iter(pages).map(|page| {
+            let page_ancestors = ancestors.get(&page.id).map_or(&[][..], Vec::as_slice);
+            fetch_and_parse_page(page, page_ancestors, &mention_titles, config)
+        });
+        let mut parsed_pages = if config.preserve_order {
+            page_fetches.buffered(config.concurrency).boxed_local()
+        } else {
+            page_fetches.buffer_unordered(config.concurrency).boxed_local()
+        };
+        // Ctrl-C is only acted on between pages, so the notes of the page being
+        // imported are all added and recorded before the run stops
+        let interrupt = tokio::signal::ctrl_c();
+        tokio::pin!(interrupt);
+        loop {
+            progress.draw(summary.added);
+            let parsed_page = tokio::select! {
+                parsed_page = parsed_pages.next() => parsed_page,
+                _ = &mut interrupt => {
+                    progress.clear();
+                    println!("\nInterrupted, stopping before the next page");
+                    summary.interrupted = true;
+                    break;
+                },
+            };
+            let Some(parsed_page) = parsed_page else {
+                progress.clear();
+                break;
+            };
+            progress.next_page();
+            // Pages still being fetched are dropped along with the stream
+            if summary.remaining(config) == 0 {
+                summary.limit_reached = true;
+                break;
+            }
+            summary.processed_pages += 1;
+            // Search can return pages whose blocks the integration can't read
+            let parsed_page = match parsed_page {
+                Err(Error::NotFound(message)) => {
+                    warn!("Skipping a page that isn't shared with the integration: {}", message);
+                    summary.inaccessible += 1;
+                    continue;
+                },
+                result => result?,
+            };
+            add_counts(&mut summary.skipped_blocks, parsed_page.skipped_blocks);
+            let mut page_decks = parsed_page.decks;
+            for cards in page_decks.iter_mut() {
+                summary.remove_repeated(cards, &mut run_cards, config);
+            }
+            let page_title = page_decks[0].title.clone();
+            let page_id = page_decks[0].source_id.clone();
+            // Subpages are imported along with the page
+            let source_ids: Vec<String> = page_decks.iter().map(|cards| cards.source_id.clone()).collect();
+            
+            // Nothing is created for a page without cards, but pruning still
+            // removes the notes of the cards it used to have
+            if page_decks.iter().all(|cards| cards.flashcards.is_empty()) {
+                println!("Skipping page \"{}\": no flashcards found", page_title);
+                summary.empty_pages += 1;
+                if block_link.is_none() {
+                    imported_pages.extend(source_ids);
+                }
+                continue;
+            }
+            
+            println!("\n========================================");
+            println!("Processing page: \"{}\" (ID: {})", page_title, page_id);
+            println!("========================================\n");
+            
+            for mut cards in page_decks.into_iter().filter(|cards| !cards.flashcards.is_empty()) {
+                // Cards left out by the limit aren't orphans
+                if summary.remaining(config) == 0 {
+                    summary.limit_reached = true;
+                    seen_keys.extend(cards.flashcards.iter().map(|flashcard| card_key(&cards.source_id, flashcard)));
+                    continue;
+                }
+                debug!("Processing page: {} into deck \"{}\"", cards.source_id, cards.deck_name);
+                
+                if config.exports() {
+                    cards.flashcards = summary.limit_cards(&cards.flashcards, config).to_vec();
+                    println!("Collected {} flashcards for deck \"{}\"", cards.flashcards.len(), cards.deck_name);
+                    summary.added += cards.flashcards.len();
+                    summary.report_page(&cards, cards.flashcards.len(), 0);
+                    exported.push(cards);
+                    continue;
+                }
+                
+                prepare_deck(&cards.deck_name, clear_decks, config, &mut prepared_decks).await?;
+                let keys = import_flashcards(&cards, source_field, &keyed_notes, config, &mut state, &mut summary, &mut card_log).await?;
+                seen_keys.extend(keys);
+            }
+            if config.exports() {
+                continue;
+            }
+            // The rest of a page only one block came from keeps its notes
+            if block_link.is_none() {
+                imported_pages.extend(source_ids);
+            }
+            
+            if config.dry_run {
+                println!("\nCompleted previewing page \"{}\"", page_title);
+            } else {
+                println!("\nCompleted importing page \"{}\"", page_title);
+            }
+        }
+        
+        // Cards gone from an imported page are orphaned, and so are all cards of
+        // pages that no longer exist when every page was searched for
+        if config.prune && !config.exports() {
+            let orphans = orphaned_notes(&state, &seen_keys, |source| {
+                imported_pages.contains(source)
+                    || (config.imports_all_pages() && is_page_source(source) && !found_pages.contains(source))
+            });
+            prune_notes(orphans, config, &mut state, &mut summary).await?;
+        }
+        
+        // Only a run over every page says nothing else changed until now
+        if config.imports_all_pages() && config.since.is_none() && !summary.limit_reached && !summary.interrupted {
+            state.last_sync = Some(sync_started);
+        }
+    }
+    
+    if let Some(export_path) = &config.export_path {
+        write_apkg(export_path, &exported)?;
+    }
+    if let Some(export_csv_path) = &config.export_csv_path {
+        write_csv(export_csv_path, &exported, config.csv_delimiter_char()?)?;
+    }
+    
+    if keep_state {
+        state.save(&config.state_file)?;
+    }
+    
+    Ok(summary)
+}
+
+/// Parse the flashcards of blocks as returned by `fetch_page_content`, with
+/// the block each card comes from and, with `REVERSE_CARDS`, reversed copies.
+/// Nothing is fetched or stored, so images only show once `store_page_images`
+/// has run on the blocks.
+pub fn blocks_to_flashcards(blocks: &[Value], config: &Config) -> Vec<Flashcard> {
+    let (markdown, block_starts) = convert_blocks_with_sources(blocks, config);
+    let mut flashcards = parse_flashcards_with_sources(&markdown, &block_starts, config);
+    if config.reverse_cards {
+        flashcards = add_reversed_cards(flashcards);
+    }
+    flashcards
+}
+
+/// The deck of a page, named after the titles of the pages above it (root
+/// first) with `DECK_HIERARCHY`
+fn page_deck_name(title: &str, ancestors: &[String], config: &Config) -> String {
+    let path: Vec<&str> = ancestors.iter().map(String::as_str).chain([title]).collect();
+    deck_name_for_page(&path.join(&config.deck_separator), config)
+}
+
+/// Fetch a page's blocks (storing its images in Anki) and parse its flashcards.
+/// With `DECK_HEADINGS` there are cards for each deck the page names, the
+/// page's own deck first; otherwise all cards go to the page's deck. With
+/// `RECURSE_SUBPAGES` the cards of its subpages follow.
+async fn fetch_and_parse_page(
+    page: NotionPage,
+    ancestors: &[String],
+    mention_titles: &MentionTitles,
+    config: &Config,
+) -> Result<ParsedPage> {
+    let title = extract_page_title(&page);
+    
+    // Blocks read from a file are parsed as they are, without asking Notion
+    // about the pages they mention
+    if let Some(input_path) = &config.input_path {
+        let mut blocks = read_input_blocks(input_path)?;
+        if config.card_format != CardFormat::Code && config.uses_anki_connect() {
+            store_page_images(&mut blocks, config).await;
+        }
+        if config.equation_mode == EquationMode::Image && config.uses_anki_connect() {
+            store_page_equations(&mut blocks, config).await;
+        }
+        let media = store_page_media(&page, config).await;
+        let decks = page_deck_cards(&page.id, &title, &page_deck_name(&title, ancestors, config), &blocks, &media, config).await?;
+        return Ok(ParsedPage { decks, skipped_blocks: skipped_block_types(&blocks, config) });
+    }
+    
+    // The page is dumped before its blocks are fetched, so a page that fails
+    // to fetch can still be looked at
+    if let Some(dump_dir) = &config.dump_dir {
+        let metadata = json!({
+            "id": page.id,
+            "last_edited_time": page.last_edited_time,
+            "properties": page.properties,
+            "icon": page.icon,
+            "cover": page.cover,
+        });
+        dump_json(dump_dir, &format!("{}.page.json", page.id), &metadata);
+    }
+    
+    // Fetch all page blocks (with pagination), then parse them as a whole
+    let block_link = config.block_url.as_deref().and_then(parse_block_url);
+    let cached_blocks = config.block_cache.then(|| load_cached_blocks(&config.cache_dir, &page)).flatten();
+    let mut blocks = match (block_link, cached_blocks) {
+        (Some((_, block_id)), _) => fetch_block_subtree(&block_id, config).await?,
+        (None, Some(blocks)) => blocks,
+        (None, None) => {
+            let fetched_at = now_timestamp();
+            let blocks = fetch_page_content(&page.id, config).await?;
+            if config.block_cache {
+                store_cached_blocks(&config.cache_dir, &page, fetched_at, &blocks);
+            }
+            blocks
+        },
+    };
+    if let Some(dump_dir) = &config.dump_dir {
+        dump_json(dump_dir, &format!("{}.json", page.id), &blocks);
+    }
+    prepare_blocks(&mut blocks, mention_titles, config).await;
+    
+    let deck_name = page_deck_name(&title, ancestors, config);
+    let mut parsed = ParsedPage {
+        decks: page_deck_cards(&page.id, &title, &deck_name, &blocks, &store_page_media(&page, config).await, config).await?,
+        skipped_blocks: skipped_block_types(&blocks, config),
+    };
+    if config.recurse_subpages {
+        fetch_subpage_cards(&page.id, &deck_name, &blocks, mention_titles, config, &mut parsed).await?;
+    }
+    Ok(parsed)
+}
+
+/// Resolve the page mentions in fetched blocks and store their images in Anki
+async fn prepare_blocks(blocks: &mut [Value], mention_titles: &MentionTitles, config: &Config) {
+    resolve_page_mentions(blocks, mention_titles, config).await;
+    
+    // Code-block cards are plain text, so images can never end up on them
+    if config.card_format != CardFormat::Code && config.uses_anki_connect() {
+        store_page_images(blocks, config).await;
+    }
+    if config.equation_mode == EquationMode::Image && config.uses_anki_connect() {
+        store_page_equations(blocks, config).await;
+    }
+}
+
+/// Cards of the subpages a page's `child_page` blocks lead to, and of their
+/// subpages in turn. A subpage gets a subdeck of its parent's deck named after
+/// it, or shares the parent's deck when `DECK_PER_PAGE` is off. They are
+/// added to the `parsed` page.
+async fn fetch_subpage_cards(
+    page_id: &str,
+    deck_name: &str,
+    blocks: &[Value],
+    mention_titles: &MentionTitles,
+    config: &Config,
+    parsed: &mut ParsedPage,
+) -> Result<()> {
+    let subpage_deck = |parent_deck: &str, title: &str| {
+        if config.deck_per_page {
+            format!("{}{}{}", parent_deck, config.deck_separator, title)
+        } else {
+            parent_deck.to_string()
+        }
+    };
+    // A page moved under one of its own subpages would otherwise loop forever
+    let mut visited = HashSet::from([page_id.to_string()]);
+    let mut subpages: VecDeque<(String, String, String)> = child_pages(blocks)
+        .into_iter()
+        .map(|(id, title)| {
+            let deck = subpage_deck(deck_name, &title);
+            (id, title, deck)
+        })
+        .collect();
+    while let Some((id, title, deck)) = subpages.pop_front() {
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        let mut blocks = match fetch_page_content(&id, config).await {
+            Err(Error::NotFound(message)) => {
+                warn!("Skipping subpage \"{}\" that isn't shared with the integration: {}", title, message);
+                continue;
+            },
+            result => result?,
+        };
+        if let Some(dump_dir) = &config.dump_dir {
+            dump_json(dump_dir, &format!("{}.json", id), &blocks);
+        }
+        prepare_blocks(&mut blocks, mention_titles, config).await;
+        for (child_id, child_title) in child_pages(&blocks) {
+            let child_deck = subpage_deck(&deck, &child_title);
+            subpages.push_back((child_id, child_title, child_deck));
+        }
+        // Only the id and title of a subpage are known, not its icon
+        parsed.decks.extend(page_deck_cards(&id, &title, &deck, &blocks, &PageMedia::default(), config).await?);
+        add_counts(&mut parsed.skipped_blocks, skipped_block_types(&blocks, config));
+    }
+    Ok(())
+}
+
+/// Parse the flashcards of a page's blocks into `deck_name`, or with
+/// `DECK_HEADINGS` once per deck the page names
+async fn page_deck_cards(
+    page_id: &str,
+    title: &str,
+    deck_name: &str,
+    blocks: &[Value],
+    media: &PageMedia,
+    config: &Config,
+) -> Result<Vec<DeckCards>> {
+    let sections = if config.deck_headings {
+        split_deck_sections(blocks)
+    } else {
+        vec![(None, blocks)]
+    };
+    let mut page_decks = Vec::with_capacity(sections.len());
+    for (section_deck, section_blocks) in sections {
+        let mut flashcards = blocks_to_flashcards(section_blocks, config);
+        // Audio only ends up in Anki's media folder when importing for real
+        if config.tts_enabled && config.uses_anki_connect() {
+            add_front_audio(&mut flashcards, config).await;
+        }
+        if config.block_comments {
+            add_block_comments(&mut flashcards, config).await;
+        }
+        apply_card_templates(&mut flashcards, title, media, config);
+        page_decks.push(DeckCards {
+            source_url: notion_url(page_id),
+            source_id: page_id.to_string(),
+            deck_name: section_deck.unwrap_or_else(|| deck_name.to_string()),
+            tags: page_tags(title, config),
+            title: title.to_string(),
+            flashcards,
+        });
+    }
+    Ok(page_decks)
+}
+
+/// Add the Notion comments on the block behind each card to its answer. Cards
+/// of one block share its comments; mapped cards have no answer to add them
+/// to. The first failure is logged and the remaining cards go without, as do
+/// all cards of the run once Notion refused the integration access to comments.
+async fn add_block_comments(flashcards: &mut [Flashcard], config: &Config) {
+    if config.comments_unreadable.get().is_some() {
+        return;
+    }
+    let mut block_comments: HashMap<String, Vec<String>> = HashMap::new();
+    for flashcard in flashcards.iter_mut().filter(|card| card.fields.is_empty()) {
+        // Drop the `#n` and `:reversed` that tell the cards of a block apart
+        let Some(block_id) = flashcard.source_block.as_deref().and_then(|block| block.split(['#', ':']).next()) else {
+            continue;
+        };
+        if !block_comments.contains_key(block_id) {
+            match fetch_block_comments(block_id, config).await {
+                Ok(Some(comments)) => {
+                    block_comments.insert(block_id.to_string(), comments);
+                },
+                Ok(None) => {
+                    if config.comments_unreadable.set(()).is_ok() {
+                        warn!("Skipping comments: the integration lacks the \"Read comments\" capability");
+                    }
+                    return;
+                },
+                Err(error) => {
+                    warn!("Skipping comments, fetching the comments of block {} failed: {}", block_id, error);
+                    return;
+                },
+            }
+        }
+        append_comments(flashcard, &block_comments[block_id]);
+    }
+}
+
+/// The page a `BLOCKS_INPUT` file stands for: the one dumped next to it, else
+/// one titled after the file, with the id of the page it was dumped from when
+/// it's named `<page id>.json`
+fn input_page(input_path: &str) -> NotionPage {
+    let metadata = fs::read_to_string(page_metadata_path(Path::new(input_path))).ok();
+    if let Some(page) = metadata.and_then(|metadata| serde_json::from_str(&metadata).ok()) {
+        return page;
+    }
+    let name = Path::new(input_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| input_path.to_string());
+    NotionPage {
+        id: normalize_page_id(&name).unwrap_or_else(|| name.clone()),
+        last_edited_time: String::new(),
+        properties: Value::Null,
+        title: Some(name),
+        parent_page_id: None,
+        parent_database_id: None,
+        icon: Value::Null,
+        cover: Value::Null,
+    }
+}
+
+/// Blocks of a `BLOCKS_INPUT` file, in the shape `fetch_page_content` returns
+fn read_input_blocks(input_path: &str) -> Result<Vec<Value>> {
+    let content = fs::read_to_string(input_path)
+        .map_err(|e| Error::Config(format!("Failed to read input file {}: {}", input_path, e)))?;
+    serde_json::from_str(&content)
+        .map_err(|e| Error::Config(format!("Failed to parse input file {} as a JSON array of blocks: {}", input_path, e)))
+}
+
+/// Write JSON to `<dump_dir>/<file_name>` for `--dump`. A failed write only
+/// costs the dump, so it doesn't stop the import.
+fn dump_json(dump_dir: &str, file_name: &str, value: &impl Serialize) {
+    let path = Path::new(dump_dir).join(file_name);
+    let result = fs::create_dir_all(dump_dir)
+        .and_then(|_| fs::write(&path, serde_json::to_string_pretty(value)?));
+    match result {
+        Ok(()) => debug!("Dumped {}", path.display()),
+        Err(error) => warn!("Failed to dump {}: {}", path.display(), error),
+    }
+}
+
+/// Where the page dumped with a blocks file is: `<page id>.page.json` next to
+/// `<page id>.json`, holding the page as Notion's search returned it
+fn page_metadata_path(blocks_path: &Path) -> std::path::PathBuf {
+    let stem = blocks_path.file_stem().unwrap_or_default().to_string_lossy();
+    blocks_path.with_file_name(format!("{}.page.json", stem))
+}
+
+/// Fetch every row of a Notion database as a card for the deck named after it
+async fn fetch_database_cards(database_id: &str, config: &Config) -> Result<DeckCards> {
+    let database_title = fetch_database_title(database_id, config).await?;
+    println!("\n========================================");
+    println!("Processing database: \"{}\" (ID: {})", database_title, database_id);
+    println!("========================================\n");
+    
+    let mut rows = query_database(database_id, config).await?;
+    let mention_titles = MentionTitles::default();
+    for row in &mut rows {
+        resolve_page_mentions(std::slice::from_mut(&mut row.properties), &mention_titles, config).await;
+    }
+    println!("Found {} database rows", rows.len());
+    
+    // Properties mapped to fields the note type doesn't have are dropped
+    let mut mappings = config.field_mappings()?;
+    if !mappings.is_empty() && config.uses_anki_connect() {
+        let model_fields = model_field_names(config).await?;
+        mappings.retain(|(property, field)| {
+            let known = model_fields.contains(field);
+            if !known {
+                warn!("Skipping property \"{}\": the note type has no field \"{}\"", property, field);
+            }
+            known
+        });
+        if mappings.is_empty() {
+            return Err(Error::Config("none of the FIELD_MAP fields exist in the note type".to_string()));
+        }
+    }
+    
+    let mut flashcards = database_row_flashcards(&rows, &mappings, config);
+    if config.reverse_cards {
+        flashcards = add_reversed_cards(flashcards);
+    }
+    if config.block_comments {
+        add_block_comments(&mut flashcards, config).await;
+    }
+    apply_card_templates(&mut flashcards, &database_title, &PageMedia::default(), config);
+    Ok(DeckCards {
+        source_id: database_source_id(database_id),
+        source_url: notion_url(database_id),
+        deck_name: deck_name_for_page(&database_title, config),
+        tags: page_tags(&database_title, config),
+        title: database_title,
+        flashcards,
+    })
+}
+
+/// Create the deck and, with `CLEAR_DECK`, clear it the first time it is seen
+async fn prepare_deck(
+    deck_name: &str,
+    clear: bool,
+    config: &Config,
+    prepared_decks: &mut HashSet<String>,
+) -> Result<()> {
+    if !config.uses_anki_connect() || !prepared_decks.insert(deck_name.to_string()) {
+        return Ok(());
+    }
+    
+    // Only new decks get DECK_OPTIONS, so options changed in Anki since stay
+    let new_deck = match &config.deck_options {
+        Some(_) => !deck_names(config).await?.iter().any(|name| name == deck_name),
+        None => false,
+    };
+    create_deck_if_not_exists(deck_name, config).await?;
+    if let Some(group_name) = config.deck_options.as_deref().filter(|_| new_deck) {
+        // The deck works with any options, so the import goes on
+        if let Err(error) = set_deck_options(deck_name, group_name, config).await {
+            warn!("Failed to set the options of deck \"{}\" to \"{}\": {}", deck_name, group_name, error);
+        }
+    }
+    
+    if clear {
+        clear_deck(deck_name, config).await?;
+    }
+    Ok(())
+}
+
+/// Add parsed flashcards to their deck, or only print them in a dry run, and
+/// report the page in the summary. Returns the keys of all the cards.
+async fn import_flashcards(
+    cards: &DeckCards,
+    source_field: Option<&str>,
+    keyed_notes: &HashMap<String, u64>,
+    config: &Config,
+    state: &mut SyncState,
+    summary: &mut Summary,
+    card_log: &mut CardLog,
+) -> Result<Vec<String>> {
+    let (added, updated) = (summary.added, summary.updated);
+    let keys = add_flashcards(cards, source_field, keyed_notes, config, state, summary, card_log).await?;
+    summary.report_page(cards, summary.added - added, summary.updated - updated);
+    Ok(keys)
+}
+
+async fn add_flashcards(
+    cards: &DeckCards,
+    source_field: Option<&str>,
+    keyed_notes: &HashMap<String, u64>,
+    config: &Config,
+    state: &mut SyncState,
+    summary: &mut Summary,
+    card_log: &mut CardLog,
+) -> Result<Vec<String>> {
+    let DeckCards { source_id, title, deck_name, tags, source_url, flashcards } = cards;
+    let record = |card_log: &mut CardLog, flashcard: &Flashcard, anki_note_id: Option<u64>, status: CardStatus, reason: Option<&str>| {
+        card_log.record(&CardRecord {
+            page_id: source_id,
+            deck: deck_name,
+            front: &flashcard.question,
+            back: &flashcard.answer,
+            anki_note_id,
+            status,
+            reason,
+        })
+    };
+    // Failures are logged and reported, and end the run with --fail-fast
+    let fail = |summary: &mut Summary, card_log: &mut CardLog, flashcard: &Flashcard, note_id: Option<u64>, reason: String| -> Result<()> {
+        warn!("Failed to import \"{}\" from \"{}\": {}", flashcard.question, title, reason);
+        record(card_log, flashcard, note_id, CardStatus::Failed, Some(&reason))?;
+        if config.fail_fast {
+            return Err(Error::AnkiConnect(format!("failed to import \"{}\": {}", flashcard.question, reason)));
+        }
+        summary.failed.push(FailedCard {
+            page: title.clone(),
+            question: flashcard.question.clone(),
+            reason,
+        });
+        Ok(())
+    };
+    println!("Total flashcards parsed: {}\n", flashcards.len());
+    let keys: Vec<String> = flashcards.iter()
+        .map(|flashcard| card_key(source_id, flashcard))
+        .collect();
+    
+    if config.dry_run {
+        let previewed = summary.limit_cards(flashcards, config);
+        for (index, flashcard) in previewed.iter().enumerate() {
+            println!("  [{}/{}] Deck: {}  Tags: {}", index + 1, flashcards.len(), deck_name, tags.join(" "));
+            println!("    Front: {}", flashcard.question);
+            println!("    Back: {}", flashcard.answer);
+            record(card_log, flashcard, None, CardStatus::Added, None)?;
+        }
+        summary.added += previewed.len();
+        return Ok(keys);
+    }
+    
+    if flashcards.is_empty() {
+        println!("No importable flashcards found in this page");
+        return Ok(keys);
+    }
+    
+    // Import all flashcards to Anki at once
+    println!("Importing {} flashcards to deck \"{}\"...", flashcards.len(), deck_name);
+    let key_tags: Vec<Option<String>> = flashcards.iter()
+        .map(|flashcard| note_key_tag(source_id, flashcard))
+        .collect();
+    let notes: Vec<Value> = flashcards.iter()
+        .zip(&key_tags)
+        .map(|(flashcard, key_tag)| {
+            let mut tags = tags.clone();
+            tags.extend(key_tag.clone());
+            let mut note = build_note(flashcard, deck_name, &tags, config);
+            // Cloze and mapped notes don't use the basic note type
+            if let Some(field) = source_field.filter(|_| !flashcard.is_cloze() && flashcard.fields.is_empty()) {
+                note["fields"][field] = json!(source_url);
+            }
+            note
+        })
+        .collect();
+    // In update mode existing notes are found by Front instead of skipped
+    let can_add = if config.update_mode {
+        vec![true; notes.len()]
+    } else {
+        can_add_notes(&notes, config).await?
+    };
+    // Cards past CARD_LIMIT are neither added nor counted as duplicates
+    let remaining = summary.remaining(config);
+    let mut new_notes = Vec::new();
+    let mut new_indices = Vec::new();
+    for (index, flashcard) in flashcards.iter().enumerate() {
+        if config.update_mode {
+            // The key tag finds the note even after its Front was edited;
+            // notes imported before key tags existed have their id mapping
+            let keyed_note = key_tags[index].as_ref().and_then(|tag| keyed_notes.get(tag)).copied();
+            if let Some(note_id) = keyed_note.or_else(|| state.notes.get(&keys[index]).copied()) {
+                // A failure here means the note was deleted in Anki
+                if update_note_fields(note_id, flashcard, config).await.is_ok() {
+                    // A mapping under the card's old question would prune the note
+                    if state.notes.get(&keys[index]) != Some(&note_id) {
+                        state.notes.retain(|_, mapped_id| *mapped_id != note_id);
+                        state.notes.insert(keys[index].clone(), note_id);
+                    }
+                    if keyed_note.is_none() {
+                        tag_note_key(note_id, key_tags[index].as_deref(), config).await;
+                    }
+                    record(card_log, flashcard, Some(note_id), CardStatus::Updated, None)?;
+                    summary.updated += 1;
+                    println!("  [{}/{}] Updated existing card", index + 1, flashcards.len());
+                    continue;
+                }
+                // The note was deleted in Anki, so the card is added again
+                state.notes.remove(&keys[index]);
+            } else if !flashcard.is_cloze() && flashcard.fields.is_empty() {
+                // Older notes are matched by Front; cloze and mapped notes have none
+                if let Some(note_id) = find_note_by_front(&flashcard.question, deck_name, config).await? {
+                    match update_note_fields(note_id, flashcard, config).await {
+                        Ok(()) => {
+                            state.notes.insert(keys[index].clone(), note_id);
+                            tag_note_key(note_id, key_tags[index].as_deref(), config).await;
+                            record(card_log, flashcard, Some(note_id), CardStatus::Updated, None)?;
+                            summary.updated += 1;
+                            println!("  [{}/{}] Updated existing card", index + 1, flashcards.len());
+                        },
+                        Err(error) => {
+                            println!("  [{}/{}] Failed to update card", index + 1, flashcards.len());
+                            fail(summary, card_log, flashcard, Some(note_id), error.to_string())?;
+                        },
+                    }
+                    continue;
+                }
+            }
+        }
+        if !can_add.get(index).copied().unwrap_or(false) {
+            record(card_log, flashcard, None, CardStatus::Skipped, None)?;
+            summary.duplicates += 1;
+            println!("  [{}/{}] Skipped duplicate card", index + 1, flashcards.len());
+        } else if new_notes.len() == remaining {
+            summary.limit_reached = true;
+        } else {
+            new_notes.push(notes[index].clone());
+            new_indices.push(index);
+        }
+    }
+    
+    // Add all remaining cards in one round-trip
+    if !new_notes.is_empty() {
+        match add_notes_to_anki(&new_notes, config).await {
+            Ok(note_ids) => {
+                for (position, index) in new_indices.iter().enumerate() {
+                    if let Some(note_id) = note_ids.get(position).copied().flatten() {
+                        state.notes.insert(keys[*index].clone(), note_id);
+                        record(card_log, &flashcards[*index], Some(note_id), CardStatus::Added, None)?;
+                        summary.added += 1;
+                        println!("  [{}/{}] Successfully added card", index + 1, flashcards.len());
+                    } else {
+                        println!("  [{}/{}] Failed to add card", index + 1, flashcards.len());
+                        fail(summary, card_log, &flashcards[*index], None, "Anki-Connect rejected the note".to_string())?;
+                    }
+                }
+            },
+            Err(error) => {
+                for index in &new_indices {
+                    fail(summary, card_log, &flashcards[*index], None, error.to_string())?;
+                }
+            },
+        }
+    }
+    Ok(keys)
+}
+
+/// Tag a note found without its key tag, so later runs find it by the key.
+/// Failing only costs that, so it doesn't fail the card.
+async fn tag_note_key(note_id: u64, key_tag: Option<&str>, config: &Config) {
+    if let Some(tag) = key_tag {
+        if let Err(error) = add_note_tag(note_id, tag, config).await {
+            warn!("Failed to tag note {} with its key: {}", note_id, error);
+        }
+    }
+}
+
+/// Mapped notes from the sources matched by `pruned_source` whose card wasn't seen this run
+fn orphaned_notes(
+    state: &SyncState,
+    seen_keys: &HashSet<String>,
+    pruned_source: impl Fn(&str) -> bool,
+) -> Vec<(String, u64)> {
+    state.notes
+        .iter()
+        .filter(|(key, _)| !seen_keys.contains(*key))
+        .filter(|(key, _)| key.rsplit_once(':').is_some_and(|(source, _)| pruned_source(source)))
+        .map(|(key, note_id)| (key.clone(), *note_id))
+        .collect()
+}
+
+/// Delete the notes of cards removed from Notion, or list them in a dry run
+async fn prune_notes(
+    orphans: Vec<(String, u64)>,
+    config: &Config,
+    state: &mut SyncState,
+    summary: &mut Summary,
+) -> Result<()> {
+    if orphans.is_empty() {
+        return Ok(());
+    }
+    
+    let note_ids: Vec<u64> = orphans.iter().map(|(_, note_id)| *note_id).collect();
+    if config.dry_run {
+        println!("\nWould delete {} notes whose cards were removed from Notion:", note_ids.len());
+        for (key, note_id) in &orphans {
+            println!("  Note {} (card {})", note_id, key);
+        }
+    } else {
+        println!("\nDeleting {} notes whose cards were removed from Notion", note_ids.len());
+        delete_notes(&note_ids, config).await?;
+        for (key, _) in &orphans {
+            state.notes.remove(key);
+        }
+    }
+    summary.pruned += note_ids.len();
+    Ok(())
+}
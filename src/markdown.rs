@@ -0,0 +1,522 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use crate::config::{CardFormat, Config, TextFormat};
+
+/// Split top-level blocks at their `heading_1` blocks for `DECK_HEADINGS`.
+/// Each heading's plain text names the deck of the blocks after it, up to the
+/// next heading; the blocks before the first heading (and after a blank one)
+/// keep the page's deck, `None`. The first section is there even when empty.
+pub fn split_deck_sections(blocks: &[Value]) -> Vec<(Option<String>, &[Value])> {
+    let mut sections = Vec::new();
+    let mut deck = None;
+    let mut start = 0;
+    for (index, block) in blocks.iter().enumerate() {
+        if block["type"] == "heading_1" {
+            sections.push((deck, &blocks[start..index]));
+            deck = extract_rich_text(&block["heading_1"]["rich_text"], TextFormat::Plain)
+                .map(|text| text.trim().to_string())
+                .filter(|text| !text.is_empty());
+            start = index + 1;
+        }
+    }
+    sections.push((deck, &blocks[start..]));
+    sections
+}
+
+/// Spaces added in front of a list item per level of nesting
+const LIST_INDENT: &str = "  ";
+
+/// Convert blocks to markdown with at most one blank line between blocks and
+/// none at the start or end
+pub fn convert_blocks_to_markdown(blocks: &[Value], config: &Config) -> String {
+    convert_blocks_with_sources(blocks, config).0
+}
+
+/// Convert blocks to markdown along with the line each top-level block
+/// starts on and its block id, in document order
+pub fn convert_blocks_with_sources(blocks: &[Value], config: &Config) -> (String, Vec<(usize, String)>) {
+    let mut block_starts = Vec::new();
+    let markdown = convert_blocks_tracked(blocks, 0, config, &mut block_starts, &mut BTreeMap::new());
+    let markdown = collapse_blank_lines(&markdown, &mut block_starts);
+    (markdown, block_starts)
+}
+
+/// Types of the blocks, nested ones included, that have no conversion and
+/// are left out of the markdown, with the number of blocks of each type
+pub fn skipped_block_types(blocks: &[Value], config: &Config) -> BTreeMap<String, usize> {
+    let mut skipped = BTreeMap::new();
+    convert_blocks_tracked(blocks, 0, config, &mut Vec::new(), &mut skipped);
+    skipped
+}
+
+/// Keep one blank line of every run between two lines with text and drop the
+/// rest, except inside code fences, where blank lines are part of the code.
+/// Block start lines are moved to where their lines end up.
+fn collapse_blank_lines(markdown: &str, block_starts: &mut [(usize, String)]) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut kept: Vec<&str> = Vec::with_capacity(lines.len());
+    // New line number of every old line, i.e. the lines kept before it
+    let mut new_lines = Vec::with_capacity(lines.len() + 1);
+    let mut in_fence = false;
+    for (index, line) in lines.iter().enumerate() {
+        new_lines.push(kept.len());
+        let blank = line.trim().is_empty() && !in_fence;
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let after_text = kept.last().is_some_and(|previous| !previous.trim().is_empty());
+        let before_text = || lines[index + 1..].iter().any(|next| !next.trim().is_empty());
+        if !blank || (after_text && before_text()) {
+            kept.push(line);
+        }
+    }
+    new_lines.push(kept.len());
+    for (start, _) in block_starts.iter_mut() {
+        *start = new_lines[(*start).min(lines.len())];
+    }
+    
+    let mut collapsed = kept.join("\n");
+    if !collapsed.is_empty() {
+        collapsed.push('\n');
+    }
+    collapsed
+}
+
+/// Text of a block's rich text, `None` when it is blank so the block is left
+/// out. Blocks without text (dividers, images, ...) don't go through this.
+fn block_text(block: &Value, block_type: &str, config: &Config) -> Option<String> {
+    field_rich_text(&block[block_type]["rich_text"], config.text_format).filter(|text| !text.trim().is_empty())
+}
+
+/// Convert blocks whose list items sit `list_depth` levels deep
+fn convert_blocks(blocks: &[Value], list_depth: usize, config: &Config, skipped: &mut BTreeMap<String, usize>) -> String {
+    convert_blocks_tracked(blocks, list_depth, config, &mut Vec::new(), skipped)
+}
+
+fn convert_blocks_tracked(
+    blocks: &[Value],
+    list_depth: usize,
+    config: &Config,
+    block_starts: &mut Vec<(usize, String)>,
+    skipped: &mut BTreeMap<String, usize>,
+) -> String {
+    let mut markdown = String::new();
+    let indent = LIST_INDENT.repeat(list_depth);
+    let html = config.text_format == TextFormat::Html;
+    // Number of the last item of the numbered list in progress, 0 outside a list
+    let mut list_number = 0;
+    
+    for block in blocks {
+        // Any other block ends the numbered list, so the next one starts over
+        if list_number > 0 && block["type"] != "numbered_list_item" {
+            if html {
+                markdown.push_str(&format!("{}</ol>\n", indent));
+            }
+            list_number = 0;
+        }
+        
+        if let Some(id) = block["id"].as_str() {
+            block_starts.push((markdown.matches('\n').count(), id.to_string()));
+        }
+        
+        if let Some(block_type) = block["type"].as_str() {
+            match block_type {
+                "heading_1" => {
+                    if let Some(text) = block_text(block, "heading_1", config) {
+                        markdown.push_str(&format!("# {}\n\n", text));
+                    }
+                },
+                "heading_2" => {
+                    if let Some(text) = block_text(block, "heading_2", config) {
+                        markdown.push_str(&format!("## {}\n\n", text));
+                    }
+                },
+                "heading_3" => {
+                    if let Some(text) = block_text(block, "heading_3", config) {
+                        markdown.push_str(&format!("### {}\n\n", text));
+                    }
+                },
+                "paragraph" => {
+                    if let Some(text) = block_text(block, "paragraph", config) {
+                        // A paragraph inside a list item continues the item,
+                        // indented under its text
+                        if list_depth > 0 {
+                            let text = text.replace('\n', &format!("\n{}", indent));
+                            markdown.push_str(&format!("{}{}\n", indent, text));
+                        } else {
+                            markdown.push_str(&format!("{}\n\n", text));
+                        }
+                    }
+                },
+                "bulleted_list_item" => {
+                    if let Some(text) = block_text(block, "bulleted_list_item", config) {
+                        markdown.push_str(&format!("{}- {}\n", indent, text));
+                    }
+                },
+                "numbered_list_item" => {
+                    // A blank item is left out and doesn't take a number
+                    if let Some(text) = block_text(block, "numbered_list_item", config) {
+                        // Notion lists can start at any number
+                        let start = if list_number == 0 {
+                            block["numbered_list_item"]["list_start_index"].as_u64().unwrap_or(1) as usize
+                        } else {
+                            list_number + 1
+                        };
+                        if html && list_number == 0 {
+                            match start {
+                                1 => markdown.push_str(&format!("{}<ol>\n", indent)),
+                                _ => markdown.push_str(&format!("{}<ol start=\"{}\">\n", indent, start)),
+                            }
+                        }
+                        list_number = start;
+                        
+                        if html {
+                            // Sub-items belong inside the <li>
+                            markdown.push_str(&format!("{}<li>{}\n", indent, text));
+                            if let Some(children) = block["children"].as_array() {
+                                markdown.push_str(&convert_blocks(children, list_depth + 1, config, skipped));
+                            }
+                            markdown.push_str(&format!("{}</li>\n", indent));
+                            continue;
+                        }
+                        markdown.push_str(&format!("{}{}. {}\n", indent, list_number, text));
+                    }
+                },
+                "to_do" => {
+                    if let Some(text) = block_text(block, "to_do", config) {
+                        let checkbox = if block["to_do"]["checked"].as_bool().unwrap_or(false) { "[x]" } else { "[ ]" };
+                        markdown.push_str(&format!("{}- {} {}\n", indent, checkbox, text));
+                    }
+                },
+                "quote" => {
+                    if let Some(text) = block_text(block, "quote", config) {
+                        // One line, so the toggle parser doesn't take the hint for a card
+                        if config.quote_as_hint {
+                            markdown.push_str(&format!("<details class=\"hint\"><summary>Hint</summary>{}</details>\n\n", text));
+                        } else {
+                            markdown.push_str(&format!("> {}\n\n", text));
+                        }
+                    }
+                },
+                "callout" => {
+                    if let Some(text) = block_text(block, "callout", config) {
+                        match block["callout"]["icon"]["emoji"].as_str() {
+                            Some(emoji) => markdown.push_str(&format!("{} {}\n\n", emoji, text)),
+                            None => markdown.push_str(&format!("{}\n\n", text)),
+                        }
+                    }
+                },
+                "code" => {
+                    let code = extract_rich_text(&block["code"]["rich_text"], TextFormat::Plain);
+                    if let Some(text) = code.filter(|text| !text.trim().is_empty()) {
+                        let language = block["code"]["language"].as_str().unwrap_or("");
+                        // Code-block cards are parsed from the fences themselves
+                        if config.text_format == TextFormat::Html && config.card_format != CardFormat::Code {
+                            markdown.push_str(&code_block_html(&text, language));
+                        } else {
+                            markdown.push_str(&format!("```{}\n{}\n```\n\n", language, escape_html(&text)));
+                        }
+                    }
+                },
+                "image" => {
+                    // Only images already stored by store_page_images can be shown
+                    if let Some(filename) = block["image"]["anki_filename"].as_str() {
+                        markdown.push_str(&format!("<img src=\"{}\">\n\n", escape_html(filename)));
+                    }
+                },
+                "bookmark" | "embed" | "video" | "file" => {
+                    if let Some(link) = link_block(&block[block_type], config.text_format) {
+                        markdown.push_str(&format!("{}\n\n", link));
+                    }
+                },
+                "equation" => {
+                    if let Some(image) = equation_image(&block["equation"]) {
+                        markdown.push_str(&format!("{}\n\n", image));
+                    } else if let Some(expression) = block["equation"]["expression"].as_str() {
+                        markdown.push_str(&format!("\\[{}\\]\n\n", expression));
+                    }
+                },
+                // Synced blocks and columns only wrap their content, which is
+                // rendered as children: columns one after another, left to right
+                "synced_block" | "column_list" | "column" => {},
+                "divider" => {
+                    // Code-block cards end at a divider; elsewhere it's a rule in the answer
+                    if config.card_format == CardFormat::Code {
+                        if !config.card_separator.trim().is_empty() {
+                            markdown.push_str(&format!("{}\n\n", config.card_separator.trim()));
+                        }
+                    } else {
+                        markdown.push_str("<hr>\n\n");
+                    }
+                },
+                "table" => {
+                    // Rows are the table's children, so render them here rather than below
+                    let rows = block["children"].as_array().map(|r| r.as_slice()).unwrap_or(&[]);
+                    let has_column_header = block["table"]["has_column_header"].as_bool().unwrap_or(false);
+                    markdown.push_str(&convert_table_to_html(rows, has_column_header));
+                    continue;
+                },
+                "toggle" => {
+                    // Toggles wrap their own children so the parser can tell where they end
+                    let text = field_rich_text(&block["toggle"]["rich_text"], config.text_format).unwrap_or_default();
+                    markdown.push_str(&format!("<details>\n<summary>{}</summary>\n\n", text));
+                    if let Some(children) = block["children"].as_array() {
+                        markdown.push_str(&convert_blocks(children, 0, config, skipped));
+                    }
+                    markdown.push_str("</details>\n\n");
+                    continue;
+                },
+                // Navigation and blocks the API can't return have no text for a
+                // card, and a template's children are only copied when it's used
+                "unsupported" | "template" | "breadcrumb" | "table_of_contents" => {
+                    debug!("Skipping {} block", block_type);
+                    continue;
+                },
+                // With RECURSE_SUBPAGES pages nested in (or linked from) this one
+                // are imported as pages of their own rather than left out
+                "child_page" | "child_database" | "link_to_page" if config.recurse_subpages => {
+                    debug!("Skipping {} block, which is imported on its own", block_type);
+                    continue;
+                },
+                _ => {
+                    // For unsupported types, just add a newline
+                    debug!("No conversion for {} block", block_type);
+                    *skipped.entry(block_type.to_string()).or_default() += 1;
+                    markdown.push('\n');
+                }
+            }
+        }
+        
+        // Render nested children fetched by fetch_blocks_recursive; the
+        // children of a list item are its sub-items, one level deeper
+        if let Some(children) = block["children"].as_array() {
+            let is_list_item = matches!(
+                block["type"].as_str(),
+                Some("bulleted_list_item" | "numbered_list_item" | "to_do")
+            );
+            let child_depth = if is_list_item { list_depth + 1 } else { list_depth };
+            markdown.push_str(&convert_blocks(children, child_depth, config, skipped));
+        }
+    }
+    if list_number > 0 && html {
+        markdown.push_str(&format!("{}</ol>\n", indent));
+    }
+    
+    markdown
+}
+
+/// Link to what a bookmark, embed, video or file block points to, showing its
+/// caption, else the file name, else the URL. Notion-hosted file URLs are
+/// signed and stop working after an hour.
+fn link_block(content: &Value, format: TextFormat) -> Option<String> {
+    let url = content["url"].as_str()
+        .or_else(|| content["external"]["url"].as_str())
+        .or_else(|| content["file"]["url"].as_str())
+        .filter(|url| !url.is_empty())?;
+    let text = field_rich_text(&content["caption"], format)
+        .filter(|caption| !caption.trim().is_empty())
+        .or_else(|| content["name"].as_str().filter(|name| !name.is_empty()).map(escape_html))
+        .unwrap_or_else(|| escape_html(url));
+    Some(match format {
+        TextFormat::Html => format!("<a href=\"{}\">{}</a>", escape_html(url), text),
+        TextFormat::Plain => format!("[{}]({})", text, escape_html(url)),
+    })
+}
+
+/// Render code as `<pre><code>` tagged with its language (e.g. `language-rust`)
+/// for highlight.js styling in the card template. Line breaks become `<br>` so
+/// the block stays on one markdown line and code like `# comment` can't be
+/// mistaken for a heading by the parser.
+fn code_block_html(code: &str, language: &str) -> String {
+    let class = language.to_lowercase().replace(' ', "-");
+    let code = escape_html(code).replace('\n', "<br>");
+    format!("<pre><code class=\"language-{}\">{}</code></pre>\n\n", escape_html(&class), code)
+}
+
+/// Build an HTML table from `table_row` blocks; cells always use the HTML
+/// rich-text path so formatting survives in the Anki field
+fn convert_table_to_html(rows: &[Value], has_column_header: bool) -> String {
+    let mut html = String::from("<table>\n");
+    
+    for (row_index, row) in rows.iter().enumerate() {
+        let tag = if has_column_header && row_index == 0 { "th" } else { "td" };
+        html.push_str("<tr>");
+        if let Some(cells) = row["table_row"]["cells"].as_array() {
+            for cell in cells {
+                let text = extract_rich_text(cell, TextFormat::Html).unwrap_or_default();
+                html.push_str(&format!("<{}>{}</{}>", tag, text, tag));
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    
+    html.push_str("</table>\n\n");
+    html
+}
+
+pub fn extract_rich_text(rich_text: &Value, format: TextFormat) -> Option<String> {
+    render_rich_text(rich_text, format, false)
+}
+
+/// Rich text as it goes into an Anki field, which Anki renders as HTML: unlike
+/// `extract_rich_text`, plain text is HTML-escaped too, so `a < b` survives
+pub fn field_rich_text(rich_text: &Value, format: TextFormat) -> Option<String> {
+    render_rich_text(rich_text, format, true)
+}
+
+fn render_rich_text(rich_text: &Value, format: TextFormat, escape_plain: bool) -> Option<String> {
+    if let Some(array) = rich_text.as_array() {
+        let mut text = String::new();
+        for item in array {
+            // Inline math goes to MathJax as-is, LaTeX must not be escaped
+            if item["type"] == "equation" {
+                if let Some(image) = equation_image(&item["equation"]) {
+                    text.push_str(&image);
+                } else if let Some(expression) = item["equation"]["expression"].as_str() {
+                    text.push_str(&format!("\\({}\\)", expression));
+                }
+                continue;
+            }
+            // Date mentions show the date itself rather than Notion's wording
+            if item["type"] == "mention" && item["mention"]["type"] == "date" {
+                if let Some(date) = mention_date(&item["mention"]["date"]) {
+                    text.push_str(&date);
+                    continue;
+                }
+            }
+            if let Some(t) = item["plain_text"].as_str() {
+                match format {
+                    TextFormat::Plain if escape_plain => text.push_str(&escape_html(t)),
+                    TextFormat::Plain => text.push_str(t),
+                    TextFormat::Html => {
+                        let html = annotate_html(&escape_html(t), &item["annotations"]);
+                        // Links wrap the formatted text so bold stays inside the anchor
+                        match rich_text_link(item) {
+                            Some(url) => text.push_str(&format!("<a href=\"{}\">{}</a>", escape_html(url), html)),
+                            None => text.push_str(&html),
+                        }
+                    },
+                }
+            }
+        }
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// The image stored for an equation by store_page_equations, with its LaTeX as alt text
+fn equation_image(equation: &Value) -> Option<String> {
+    let filename = equation["anki_filename"].as_str()?;
+    let expression = equation["expression"].as_str().unwrap_or("");
+    Some(format!("<img src=\"{}\" alt=\"{}\">", escape_html(filename), escape_html(expression)))
+}
+
+/// `start`, or `start → end` for a range, with times shown to the minute
+fn mention_date(date: &Value) -> Option<String> {
+    let format = |value: &str| {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| value.to_string())
+    };
+    let start = format(date["start"].as_str()?);
+    match date["end"].as_str() {
+        Some(end) => Some(format!("{} → {}", start, format(end))),
+        None => Some(start),
+    }
+}
+
+/// URL of a rich-text item, from `href` or the text link
+fn rich_text_link(item: &Value) -> Option<&str> {
+    item["href"].as_str()
+        .or_else(|| item["text"]["link"]["url"].as_str())
+}
+
+/// Wrap already-escaped text in tags for each enabled Notion annotation.
+/// Tags are applied innermost first so combined annotations nest correctly,
+/// e.g. bold+italic becomes `<b><i>text</i></b>`. A text or background color
+/// becomes the outermost `<span>`.
+fn annotate_html(text: &str, annotations: &Value) -> String {
+    let mut html = text.to_string();
+    for (annotation, tag) in [
+        ("code", "code"),
+        ("strikethrough", "s"),
+        ("underline", "u"),
+        ("italic", "i"),
+        ("bold", "b"),
+    ] {
+        if annotations[annotation].as_bool().unwrap_or(false) {
+            html = format!("<{}>{}</{}>", tag, html, tag);
+        }
+    }
+    if let Some(style) = annotations["color"].as_str().and_then(color_style) {
+        html = format!("<span style=\"{}\">{}</span>", style, html);
+    }
+    html
+}
+
+/// CSS for a Notion color such as `red` or `yellow_background`, in the shades
+/// Notion shows them in. `default` and unknown colors get no style.
+fn color_style(color: &str) -> Option<String> {
+    let (name, property) = match color.strip_suffix("_background") {
+        Some(name) => (name, "background-color"),
+        None => (color, "color"),
+    };
+    let (text, background) = match name {
+        "gray" => ("#787774", "#f1f1ef"),
+        "brown" => ("#9f6b53", "#f4eeee"),
+        "orange" => ("#d9730d", "#fbecdd"),
+        "yellow" => ("#cb912f", "#fbf3db"),
+        "green" => ("#448361", "#edf3ec"),
+        "blue" => ("#337ea9", "#e7f3f8"),
+        "purple" => ("#9065b0", "#f4f0f7"),
+        "pink" => ("#c14c8a", "#f9eef3"),
+        "red" => ("#d44c47", "#fdebec"),
+        _ => return None,
+    };
+    let css = if property == "color" { text } else { background };
+    Some(format!("{}:{}", property, css))
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Tags of the HTML blocks the converter emits; line breaks next to them are
+/// layout of the markup, not of the text
+const BLOCK_TAGS: [&str; 10] = ["table", "tr", "ol", "ul", "li", "pre", "details", "summary", "hr", "div"];
+
+/// Whether `tag` (the text of one `<...>` without the brackets) opens or closes a block
+fn is_block_tag(tag: &str) -> bool {
+    let name = tag.trim_start_matches('/');
+    let name = name.split([' ', '/']).next().unwrap_or_default();
+    BLOCK_TAGS.contains(&name)
+}
+
+/// Turn multi-line card text into Anki field HTML, where plain newlines would
+/// collapse into spaces: lines are joined with `<br>` (except around block
+/// markup like tables and lists) and list indentation becomes `&nbsp;`
+pub(crate) fn field_html(text: &str) -> String {
+    let mut html = String::new();
+    let mut previous_is_block = true;
+    for line in text.lines() {
+        let content = line.trim_start();
+        let starts_block = content.strip_prefix('<').is_some_and(|tag| is_block_tag(tag.split('>').next().unwrap_or_default()));
+        if !html.is_empty() && !previous_is_block && !starts_block {
+            html.push_str("<br>");
+        }
+        html.push_str(&"&nbsp;".repeat(line.len() - content.len()));
+        html.push_str(content);
+        
+        previous_is_block = content
+            .strip_suffix('>')
+            .and_then(|rest| rest.rsplit_once('<'))
+            .is_some_and(|(_, tag)| is_block_tag(tag));
+    }
+    html
+}
@@ -0,0 +1,319 @@
+use base64::Engine;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{debug, warn};
+
+use crate::anki::anki_post;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::markdown::escape_html;
+use crate::notion::NotionPage;
+use crate::parser::Flashcard;
+use crate::state::stable_hash;
+
+/// URL of an image block, either a Notion-hosted file or an external link
+fn image_block_url(block: &Value) -> Option<&str> {
+    block["image"]["file"]["url"].as_str()
+        .or_else(|| block["image"]["external"]["url"].as_str())
+}
+
+fn collect_image_urls(blocks: &[Value], urls: &mut Vec<String>) {
+    for block in blocks {
+        if block["type"] == "image" {
+            if let Some(url) = image_block_url(block) {
+                urls.push(url.to_string());
+            }
+        }
+        if let Some(children) = block["children"].as_array() {
+            collect_image_urls(children, urls);
+        }
+    }
+}
+
+fn assign_image_filenames(blocks: &mut [Value], filenames: &HashMap<String, String>) {
+    for block in blocks.iter_mut() {
+        if block["type"] == "image" {
+            let filename = image_block_url(block).and_then(|url| filenames.get(url)).cloned();
+            if let Some(filename) = filename {
+                block["image"]["anki_filename"] = json!(filename);
+            }
+        }
+        if let Some(children) = block["children"].as_array_mut() {
+            assign_image_filenames(children, filenames);
+        }
+    }
+}
+
+/// Download every image in the page into Anki's media folder and record the
+/// stored filename on each image block for convert_blocks_to_markdown.
+/// Notion-hosted image URLs are signed and expire, so this has to run right
+/// after the blocks are fetched.
+pub async fn store_page_images(blocks: &mut [Value], config: &Config) {
+    let mut urls = Vec::new();
+    collect_image_urls(blocks, &mut urls);
+    
+    let mut filenames = HashMap::new();
+    for url in urls {
+        if filenames.contains_key(&url) {
+            continue;
+        }
+        match store_image_in_anki(&url, config).await {
+            Ok(filename) => {
+                filenames.insert(url, filename);
+            },
+            Err(error) => warn!("Failed to store image {}: {}", url, error),
+        }
+    }
+    
+    assign_image_filenames(blocks, &filenames);
+}
+
+/// Stable media filename for an image URL. The query string is ignored
+/// because Notion re-signs file URLs on every request.
+fn image_media_filename(url: &str) -> String {
+    let path = url.split('?').next().unwrap_or(url);
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .filter(|e| e.len() <= 4 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("png")
+        .to_lowercase();
+    
+    // A stable hash, so the same image maps to the same file across runs
+    format!("notion2anki-{:016x}.{}", stable_hash(path), extension)
+}
+
+pub async fn store_image_in_anki(url: &str, config: &Config) -> Result<String> {
+    let filename = image_media_filename(url);
+    
+    debug!("Downloading image: {}", url);
+    
+    let image_response = config.http_client().get(url).send().await?.error_for_status()?;
+    let image_bytes = image_response.bytes().await?;
+    
+    store_media_file(&filename, &image_bytes, config).await?;
+    Ok(filename)
+}
+
+/// Put a file into Anki's media folder, replacing one with the same name
+async fn store_media_file(filename: &str, bytes: &[u8], config: &Config) -> Result<()> {
+    let store_media_data = json!({
+        "action": "storeMediaFile",
+        "version": 6,
+        "params": {
+            "filename": filename,
+            "data": base64::engine::general_purpose::STANDARD.encode(bytes)
+        }
+    });
+    
+    debug!("Storing media file \"{}\" ({} bytes)", filename, bytes.len());
+    
+    let response = anki_post(&store_media_data, config).await?;
+    
+    let response_text = response.text().await?;
+    
+    debug!("Store media file response: {}", response_text);
+    
+    let response_json: Value = serde_json::from_str(&response_text)?;
+    if response_json["error"].is_null() {
+        Ok(())
+    } else {
+        Err(Error::AnkiConnect(response_json["error"].to_string()))
+    }
+}
+
+fn collect_equations(value: &Value, expressions: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            // Equation blocks and inline equations in rich text share this shape
+            if value["type"] == "equation" {
+                if let Some(expression) = value["equation"]["expression"].as_str() {
+                    expressions.push(expression.to_string());
+                }
+            }
+            object.values().for_each(|value| collect_equations(value, expressions));
+        },
+        Value::Array(array) => array.iter().for_each(|value| collect_equations(value, expressions)),
+        _ => {},
+    }
+}
+
+fn assign_equation_filenames(value: &mut Value, filenames: &HashMap<String, String>) {
+    if value["type"] == "equation" {
+        let filename = value["equation"]["expression"].as_str().and_then(|expression| filenames.get(expression)).cloned();
+        if let Some(filename) = filename {
+            value["equation"]["anki_filename"] = json!(filename);
+        }
+    }
+    match value {
+        Value::Object(object) => object.values_mut().for_each(|value| assign_equation_filenames(value, filenames)),
+        Value::Array(array) => array.iter_mut().for_each(|value| assign_equation_filenames(value, filenames)),
+        _ => {},
+    }
+}
+
+/// Render every equation of the page, block or inline, with the
+/// `EQUATION_RENDER_URL` service and record the stored image on it for
+/// convert_blocks_to_markdown. Equations that fail to render stay LaTeX.
+pub async fn store_page_equations(blocks: &mut [Value], config: &Config) {
+    let Some(render_url) = &config.equation_render_url else {
+        return;
+    };
+    let mut expressions = Vec::new();
+    blocks.iter().for_each(|block| collect_equations(block, &mut expressions));
+    
+    let mut filenames = HashMap::new();
+    for expression in expressions {
+        if filenames.contains_key(&expression) {
+            continue;
+        }
+        match store_equation_in_anki(render_url, &expression, config).await {
+            Ok(filename) => {
+                filenames.insert(expression, filename);
+            },
+            Err(error) => warn!("Failed to render equation \"{}\": {}", expression, error),
+        }
+    }
+    
+    blocks.iter_mut().for_each(|block| assign_equation_filenames(block, &filenames));
+}
+
+async fn store_equation_in_anki(render_url: &str, expression: &str, config: &Config) -> Result<String> {
+    debug!("Rendering equation: {}", expression);
+    
+    let response = config.http_client()
+        .get(render_url)
+        .query(&[("latex", expression)])
+        .send()
+        .await?
+        .error_for_status()?;
+    let extension = match response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()) {
+        Some(content_type) if content_type.contains("svg") => "svg",
+        _ => "png",
+    };
+    let image_bytes = response.bytes().await?;
+    
+    let filename = format!("notion2anki-equation-{:016x}.{}", stable_hash(expression), extension);
+    store_media_file(&filename, &image_bytes, config).await?;
+    Ok(filename)
+}
+
+/// What the `{icon}` and `{cover}` placeholders of the card templates show
+#[derive(Debug, Clone, Default)]
+pub struct PageMedia {
+    /// The page's emoji, or an `<img>` of its icon image
+    pub icon: String,
+    /// An `<img>` of the page's cover
+    pub cover: String,
+}
+
+/// File URL of a Notion icon or cover: hosted by Notion, external, or a
+/// workspace's custom emoji
+fn page_file_url(file: &Value) -> Option<&str> {
+    ["file", "external", "custom_emoji"].iter().find_map(|kind| file[kind]["url"].as_str())
+}
+
+/// Store the icon and cover images of a page in Anki's media folder for the
+/// card templates. An emoji icon is used as it is; images that fail to
+/// download are left out with a warning.
+pub async fn store_page_media(page: &NotionPage, config: &Config) -> PageMedia {
+    let mut media = PageMedia::default();
+    if config.uses_template_placeholder("icon") {
+        if let Some(emoji) = page.icon["emoji"].as_str() {
+            media.icon = emoji.to_string();
+        } else if let Some(url) = page_file_url(&page.icon) {
+            media.icon = store_template_image(url, config).await;
+        }
+    }
+    if config.uses_template_placeholder("cover") {
+        if let Some(url) = page_file_url(&page.cover) {
+            media.cover = store_template_image(url, config).await;
+        }
+    }
+    media
+}
+
+/// An `<img>` of the image at `url` once it's stored in Anki, else nothing
+async fn store_template_image(url: &str, config: &Config) -> String {
+    if !config.uses_anki_connect() {
+        return String::new();
+    }
+    match store_image_in_anki(url, config).await {
+        Ok(filename) => format!("<img src=\"{}\">", escape_html(&filename)),
+        Err(error) => {
+            warn!("Failed to store page image {}: {}", url, error);
+            String::new()
+        },
+    }
+}
+
+/// Synthesize the Front of every basic card with the `TTS_URL` service and
+/// store it as the card's audio. The first failure (usually an unreachable
+/// service) is logged and the remaining cards are left without audio.
+pub async fn add_front_audio(flashcards: &mut [Flashcard], config: &Config) {
+    let Some(tts_url) = &config.tts_url else {
+        return;
+    };
+    for flashcard in flashcards.iter_mut() {
+        // Cloze and mapped notes have no Front field
+        if flashcard.is_cloze() || !flashcard.fields.is_empty() {
+            continue;
+        }
+        let text = speech_text(&flashcard.question);
+        if text.is_empty() {
+            continue;
+        }
+        match store_speech_in_anki(tts_url, &text, config).await {
+            Ok(filename) => flashcard.audio = Some(filename),
+            Err(error) => {
+                warn!("Skipping audio, text-to-speech failed for \"{}\": {}", text, error);
+                return;
+            },
+        }
+    }
+}
+
+/// What is read aloud for a field: its text without HTML tags or entities
+fn speech_text(field: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in field.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {},
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+async fn store_speech_in_anki(tts_url: &str, text: &str, config: &Config) -> Result<String> {
+    debug!("Synthesizing speech: {}", text);
+    
+    let response = config.http_client()
+        .get(tts_url)
+        .query(&[("text", text)])
+        .send()
+        .await?
+        .error_for_status()?;
+    let extension = match response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()) {
+        Some(content_type) if content_type.contains("wav") => "wav",
+        Some(content_type) if content_type.contains("ogg") => "ogg",
+        _ => "mp3",
+    };
+    let audio_bytes = response.bytes().await?;
+    
+    // The same text maps to the same file, so a later run replaces it
+    let filename = format!("notion2anki-tts-{:016x}.{}", stable_hash(text), extension);
+    store_media_file(&filename, &audio_bytes, config).await?;
+    Ok(filename)
+}
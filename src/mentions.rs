@@ -0,0 +1,76 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::notion::fetch_pages_by_id;
+
+/// Titles of mentioned pages, shared by all pages of a run so each mentioned
+/// page is only fetched once. `None` marks pages whose title couldn't be fetched.
+#[derive(Debug, Default)]
+pub struct MentionTitles(Mutex<HashMap<String, Option<String>>>);
+
+/// ID of the page a rich-text item mentions
+fn page_mention_id(item: &Value) -> Option<&str> {
+    if item["type"] == "mention" && item["mention"]["type"] == "page" {
+        item["mention"]["page"]["id"].as_str()
+    } else {
+        None
+    }
+}
+
+/// Page mentions can sit in any rich text (blocks, table cells, database
+/// properties), so the whole value is searched
+fn collect_page_mentions(value: &Value, ids: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(id) = page_mention_id(value) {
+                ids.push(id.to_string());
+            }
+            map.values().for_each(|child| collect_page_mentions(child, ids));
+        },
+        Value::Array(items) => items.iter().for_each(|child| collect_page_mentions(child, ids)),
+        _ => {},
+    }
+}
+
+fn assign_mention_titles(value: &mut Value, titles: &HashMap<String, Option<String>>) {
+    if let Some(Some(title)) = page_mention_id(value).and_then(|id| titles.get(id)) {
+        value["plain_text"] = json!(title);
+    }
+    match value {
+        Value::Object(map) => map.values_mut().for_each(|child| assign_mention_titles(child, titles)),
+        Value::Array(items) => items.iter_mut().for_each(|child| assign_mention_titles(child, titles)),
+        _ => {},
+    }
+}
+
+/// Replace the text of every page mention with the mentioned page's current
+/// title. Mentions whose page can't be fetched keep the text Notion sent.
+pub async fn resolve_page_mentions(values: &mut [Value], titles: &MentionTitles, config: &Config) {
+    let mut ids = Vec::new();
+    for value in values.iter() {
+        collect_page_mentions(value, &mut ids);
+    }
+    
+    for id in ids {
+        if titles.0.lock().unwrap().contains_key(&id) {
+            continue;
+        }
+        debug!("Fetching title of mentioned page {}", id);
+        let title = match fetch_pages_by_id(std::slice::from_ref(&id), config).await {
+            Ok(pages) => pages.first().and_then(|page| page.title.clone()),
+            Err(error) => {
+                warn!("Failed to fetch mentioned page {}: {}", id, error);
+                None
+            },
+        };
+        titles.0.lock().unwrap().insert(id, title);
+    }
+    
+    let titles = titles.0.lock().unwrap();
+    for value in values.iter_mut() {
+        assign_mention_titles(value, &titles);
+    }
+}
@@ -0,0 +1,620 @@
+use reqwest::{Method, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::{Config, TextFormat, TitleMatch};
+use crate::error::{Error, Result};
+use crate::markdown::{extract_rich_text, field_rich_text};
+
+#[derive(Deserialize, Debug)]
+#[serde(from = "RawNotionPage")]
+pub struct NotionPage {
+    pub id: String,
+    pub last_edited_time: String,
+    pub properties: Value,
+    /// Plain text of the title property, `None` for untitled pages
+    pub title: Option<String>,
+    /// Page this one is a subpage of, `None` in a workspace, database or block
+    pub parent_page_id: Option<String>,
+    /// Database this page is a row of
+    pub parent_database_id: Option<String>,
+    /// Notion's `icon` object, an emoji or a file; `null` without an icon
+    pub icon: Value,
+    /// Notion's `cover` object, a file; `null` without a cover
+    pub cover: Value,
+}
+
+/// A page or database row as Notion sends it, before its title is read
+#[derive(Deserialize)]
+struct RawNotionPage {
+    id: String,
+    #[serde(default)]
+    last_edited_time: String,
+    properties: Value,
+    #[serde(default)]
+    parent: Value,
+    #[serde(default)]
+    icon: Value,
+    #[serde(default)]
+    cover: Value,
+}
+
+impl From<RawNotionPage> for NotionPage {
+    fn from(page: RawNotionPage) -> Self {
+        NotionPage {
+            title: property_title(&page.properties),
+            parent_page_id: page.parent["page_id"].as_str().map(|id| id.to_string()),
+            parent_database_id: page.parent["database_id"].as_str().map(|id| id.to_string()),
+            id: page.id,
+            last_edited_time: page.last_edited_time,
+            properties: page.properties,
+            icon: page.icon,
+            cover: page.cover,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct NotionSearchResponse<T = NotionPage> {
+    results: Vec<T>,
+    has_more: bool,
+    next_cursor: Option<String>,
+}
+
+pub fn extract_page_title(page: &NotionPage) -> String {
+    // Fallback to page ID if title cannot be extracted
+    page.title.clone().unwrap_or_else(|| format!("Page-{}", page.id.chars().take(8).collect::<String>()))
+}
+
+/// Title of any page, database rows included, whose title property can have
+/// any name (it is `title` on pages)
+fn property_title(properties: &Value) -> Option<String> {
+    properties
+        .as_object()?
+        .values()
+        .find(|property| property["type"] == "title")
+        .and_then(|property| extract_rich_text(&property["title"], TextFormat::Plain))
+        .filter(|title| !title.is_empty())
+}
+
+/// Whether a page title passes `PAGE_TITLE_FILTER` as matched by `PAGE_TITLE_MATCH`
+pub fn title_matches(title: &str, filter: &str, mode: TitleMatch) -> bool {
+    match mode {
+        TitleMatch::Prefix => title.starts_with(filter),
+        TitleMatch::Contains => title.contains(filter),
+    }
+}
+
+/// Link that opens a page or database in Notion
+pub fn notion_url(id: &str) -> String {
+    format!("https://notion.so/{}", id.replace('-', ""))
+}
+
+/// Page and block ID of a link to a block, like
+/// `https://www.notion.so/Page-Title-<page id>?pvs=4#<block id>`
+pub fn parse_block_url(url: &str) -> Option<(String, String)> {
+    let (path, fragment) = url.trim().split_once('#')?;
+    let block_id = normalize_page_id(fragment)?;
+    let path = path.split('?').next().unwrap_or(path);
+    let segment = path.trim_end_matches('/').rsplit('/').next()?;
+    // The page ID ends the last path segment, after the title
+    let page_id = normalize_page_id(segment)
+        .or_else(|| normalize_page_id(segment.get(segment.len().checked_sub(32)?..)?))?;
+    Some((page_id, block_id))
+}
+
+/// Put a Notion page ID into its dashed UUID form. IDs copied from page URLs
+/// come without dashes; anything that isn't 32 hex digits is rejected.
+pub fn normalize_page_id(id: &str) -> Option<String> {
+    let hex: String = id.trim().chars().filter(|c| *c != '-').collect();
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = hex.to_lowercase();
+    Some(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+/// How many times a rate-limited Notion request is retried
+const NOTION_MAX_RETRIES: u32 = 5;
+
+async fn notion_get(url: &str, config: &Config) -> Result<Response> {
+    notion_request(Method::GET, url, None, config).await
+}
+
+async fn notion_post(url: &str, body: &Value, config: &Config) -> Result<Response> {
+    notion_request(Method::POST, url, Some(body), config).await
+}
+
+/// Send a Notion API request, retrying when Notion answers 429 Too Many Requests.
+/// Waits for `Retry-After` when present, otherwise backs off exponentially.
+async fn notion_request(
+    method: Method,
+    url: &str,
+    body: Option<&Value>,
+    config: &Config,
+) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        wait_for_request_slot(config).await;
+        let mut request = config
+            .http_client()
+            .request(method.clone(), url)
+            .header("Authorization", format!("Bearer {}", config.notion_api_key))
+            .header("Notion-Version", &config.notion_api_version);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        
+        let response = request.send().await?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= NOTION_MAX_RETRIES {
+            return Ok(response);
+        }
+        
+        let delay = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_else(|| Duration::from_secs(1 << attempt));
+        attempt += 1;
+        warn!("Notion rate limit hit, retrying in {}s (attempt {}/{})", delay.as_secs(), attempt, NOTION_MAX_RETRIES);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Space Notion requests `1 / NOTION_RATE_LIMIT` seconds apart. Each request
+/// takes the next free slot, so concurrent page fetches share the limit.
+async fn wait_for_request_slot(config: &Config) {
+    if config.notion_rate_limit <= 0.0 {
+        return;
+    }
+    let interval = Duration::from_secs_f64(1.0 / config.notion_rate_limit);
+    let slot = {
+        // A poisoned lock only means another request panicked mid-update
+        let mut next_request = config.notion_next_request.lock().unwrap_or_else(|error| error.into_inner());
+        let now = Instant::now();
+        let slot = next_request.map_or(now, |next| next.max(now));
+        *next_request = Some(slot + interval);
+        slot
+    };
+    tokio::time::sleep_until(slot.into()).await;
+}
+
+/// Error for a failed Notion request, using the message from Notion's error body.
+/// A rejected token gets its own error, since every request would fail the same way.
+fn notion_error(context: &str, status: StatusCode, response_text: &str) -> Error {
+    if status == StatusCode::UNAUTHORIZED {
+        return Error::Auth("Your NOTION_API_KEY was rejected; check the integration token and that the pages are shared with the integration.".to_string());
+    }
+    let response_json: Value = serde_json::from_str(response_text).unwrap_or_default();
+    let message = response_json["message"].as_str().unwrap_or(response_text);
+    if status == StatusCode::NOT_FOUND {
+        return Error::NotFound(format!("{}: {}", context, message));
+    }
+    Error::Notion(format!("{}: {}", context, message))
+}
+
+pub async fn fetch_all_pages(config: &Config) -> Result<Vec<NotionPage>> {
+    let pages: Vec<NotionPage> = search_all("page", config).await?;
+    for page in &pages {
+        debug!("Found page {}: {}", page.id, page.title.as_deref().unwrap_or("(untitled)"));
+    }
+    Ok(pages)
+}
+
+/// Ids of all databases shared with the integration
+pub async fn fetch_all_database_ids(config: &Config) -> Result<Vec<String>> {
+    let databases: Vec<Value> = search_all("database", config).await?;
+    Ok(databases
+        .iter()
+        .filter_map(|database| database["id"].as_str())
+        .inspect(|id| debug!("Found database {}", id))
+        .map(|id| id.to_string())
+        .collect())
+}
+
+/// Every object of one type (`page` or `database`) that search returns
+async fn search_all<T: DeserializeOwned>(object: &str, config: &Config) -> Result<Vec<T>> {
+    let url = format!("{}/search", config.notion_api_url);
+
+    let mut all_results = Vec::new();
+    let mut start_cursor: Option<String> = None;
+
+    loop {
+        let mut request_body = json!({
+            "filter": {
+                "value": object,
+                "property": "object"
+            },
+            "page_size": 100
+        });
+        if let Some(cursor) = &start_cursor {
+            request_body["start_cursor"] = json!(cursor);
+        }
+
+        debug!("Searching {}s - Request URL: {}", object, url);
+        debug!("Request body: {}", serde_json::to_string_pretty(&request_body).unwrap());
+
+        let response = notion_post(&url, &request_body, config).await?;
+        let status = response.status();
+        let response_text = response.text().await?;
+
+        debug!("Search {}s response: {}", object, response_text);
+
+        if !status.is_success() {
+            return Err(notion_error(&format!("failed to search {}s", object), status, &response_text));
+        }
+
+        let search_response: NotionSearchResponse<T> = serde_json::from_str(&response_text)?;
+        all_results.extend(search_response.results);
+
+        // Follow the cursor until Notion reports no more results
+        if !search_response.has_more {
+            break;
+        }
+        start_cursor = search_response.next_cursor;
+        if start_cursor.is_none() {
+            break;
+        }
+    }
+
+    Ok(all_results)
+}
+
+/// Fetch specific pages by ID instead of searching for every page
+pub async fn fetch_pages_by_id(page_ids: &[String], config: &Config) -> Result<Vec<NotionPage>> {
+    let mut pages = Vec::new();
+    
+    for page_id in page_ids {
+        let page_id = normalize_page_id(page_id)
+            .ok_or_else(|| Error::Config(format!("Invalid Notion page ID: {}", page_id)))?;
+        let url = format!("{}/pages/{}", config.notion_api_url, page_id);
+        
+        debug!("Fetching page - Request URL: {}", url);
+        
+        let response = notion_get(&url, config).await?;
+        let status = response.status();
+        let response_text = response.text().await?;
+        
+        debug!("Fetch page response: {}", response_text);
+        
+        if !status.is_success() {
+            return Err(notion_error(&format!("failed to fetch page {}", page_id), status, &response_text));
+        }
+        pages.push(serde_json::from_str(&response_text)?);
+    }
+    
+    Ok(pages)
+}
+
+/// Name of the integration the API key belongs to, which also checks the key
+pub async fn fetch_bot_name(config: &Config) -> Result<String> {
+    let url = format!("{}/users/me", config.notion_api_url);
+    
+    let response = notion_get(&url, config).await?;
+    let status = response.status();
+    let response_text = response.text().await?;
+    
+    debug!("Fetch bot user response: {}", response_text);
+    
+    if !status.is_success() {
+        return Err(notion_error("failed to fetch the integration user", status, &response_text));
+    }
+    let user: Value = serde_json::from_str(&response_text)?;
+    Ok(user["name"].as_str().unwrap_or("unnamed integration").to_string())
+}
+
+/// Text of the comments on a block (or page), oldest first. `None` when the
+/// integration may not read comments, which Notion answers with 403 Forbidden.
+pub async fn fetch_block_comments(block_id: &str, config: &Config) -> Result<Option<Vec<String>>> {
+    let mut comments = Vec::new();
+    let mut start_cursor: Option<String> = None;
+    
+    loop {
+        let mut url = format!("{}/comments?block_id={}&page_size=100", config.notion_api_url, block_id);
+        if let Some(cursor) = &start_cursor {
+            url.push_str(&format!("&start_cursor={}", cursor));
+        }
+        
+        debug!("Fetching comments - Request URL: {}", url);
+        
+        let response = notion_get(&url, config).await?;
+        let status = response.status();
+        let response_text = response.text().await?;
+        
+        debug!("Fetch comments response: {}", response_text);
+        
+        if status == StatusCode::FORBIDDEN {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(notion_error(&format!("failed to fetch comments of block {}", block_id), status, &response_text));
+        }
+        
+        let comments_json: Value = serde_json::from_str(&response_text)?;
+        if let Some(results) = comments_json["results"].as_array() {
+            comments.extend(results.iter().filter_map(|comment| comment_text(comment, config.text_format)));
+        }
+        
+        if !comments_json["has_more"].as_bool().unwrap_or(false) {
+            break;
+        }
+        start_cursor = comments_json["next_cursor"].as_str().map(|s| s.to_string());
+        if start_cursor.is_none() {
+            break;
+        }
+    }
+    
+    Ok(Some(comments))
+}
+
+/// Text of a comment object as it goes into a field, `None` when it's empty
+pub fn comment_text(comment: &Value, format: TextFormat) -> Option<String> {
+    field_rich_text(&comment["rich_text"], format)
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Title of a Notion database, falling back to its ID like untitled pages
+pub async fn fetch_database_title(database_id: &str, config: &Config) -> Result<String> {
+    let url = format!("{}/databases/{}", config.notion_api_url, database_id);
+    
+    debug!("Fetching database - Request URL: {}", url);
+    
+    let response = notion_get(&url, config).await?;
+    let status = response.status();
+    let response_text = response.text().await?;
+    
+    debug!("Fetch database response: {}", response_text);
+    
+    if !status.is_success() {
+        return Err(notion_error(&format!("failed to fetch database {}", database_id), status, &response_text));
+    }
+    let database: Value = serde_json::from_str(&response_text)?;
+    let title = extract_rich_text(&database["title"], TextFormat::Plain).unwrap_or_default();
+    if title.is_empty() {
+        Ok(format!("Database-{}", database_id.chars().take(8).collect::<String>()))
+    } else {
+        Ok(title)
+    }
+}
+
+/// Fetch every row of a Notion database; rows are pages whose properties
+/// hold the column values
+pub async fn query_database(database_id: &str, config: &Config) -> Result<Vec<NotionPage>> {
+    let url = format!("{}/databases/{}/query", config.notion_api_url, database_id);
+    
+    let mut rows = Vec::new();
+    let mut start_cursor: Option<String> = None;
+    
+    loop {
+        let mut request_body = json!({
+            "page_size": 100
+        });
+        if let Some(cursor) = &start_cursor {
+            request_body["start_cursor"] = json!(cursor);
+        }
+        
+        debug!("Querying database - Request URL: {}", url);
+        debug!("Request body: {}", serde_json::to_string_pretty(&request_body).unwrap());
+        
+        let response = notion_post(&url, &request_body, config).await?;
+        let status = response.status();
+        let response_text = response.text().await?;
+        
+        debug!("Query database response: {}", response_text);
+        
+        if !status.is_success() {
+            return Err(notion_error(&format!("failed to query database {}", database_id), status, &response_text));
+        }
+        let query_response: NotionSearchResponse = serde_json::from_str(&response_text)?;
+        rows.extend(query_response.results);
+        
+        if !query_response.has_more {
+            break;
+        }
+        start_cursor = query_response.next_cursor;
+        if start_cursor.is_none() {
+            break;
+        }
+    }
+    
+    Ok(rows)
+}
+
+pub async fn fetch_page_content(page_id: &str, config: &Config) -> Result<Vec<Value>> {
+    debug!("Fetching blocks of page {}", page_id);
+    let blocks = fetch_blocks_recursive(page_id, 0, config).await?;
+    info!("Fetched {} blocks of page {}", blocks.len(), page_id);
+    Ok(blocks)
+}
+
+type BlocksFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Value>>> + 'a>>;
+
+/// Fetch the children of a block and, for every child with `has_children`,
+/// attach its own children under a `children` key, up to `max_block_depth`
+fn fetch_blocks_recursive<'a>(
+    block_id: &'a str,
+    depth: usize,
+    config: &'a Config,
+) -> BlocksFuture<'a> {
+    Box::pin(async move {
+        let mut blocks = fetch_block_children(block_id, config).await?;
+        attach_children(&mut blocks, depth, config).await?;
+        Ok(blocks)
+    })
+}
+
+/// Fetch the children of every block with `has_children` that sits `depth`
+/// levels deep into its `children` key. Subpages and databases keep their
+/// content, which isn't part of the page.
+async fn attach_children(blocks: &mut [Value], depth: usize, config: &Config) -> Result<()> {
+    for block in blocks.iter_mut() {
+        if matches!(block["type"].as_str(), Some("child_page" | "child_database")) {
+            continue;
+        }
+        // A synced block copy shows the content of the original it was synced from
+        let synced_from = block["synced_block"]["synced_from"]["block_id"].as_str().map(|s| s.to_string());
+        if !block["has_children"].as_bool().unwrap_or(false) && synced_from.is_none() {
+            continue;
+        }
+        if depth + 1 >= config.max_block_depth {
+            debug!("Max block depth {} reached, skipping children of {}", config.max_block_depth, block["id"]);
+            continue;
+        }
+        let synced = synced_from.is_some();
+        if let Some(child_id) = synced_from.or_else(|| block["id"].as_str().map(|s| s.to_string())) {
+            let children = match fetch_blocks_recursive(&child_id, depth + 1, config).await {
+                // The original may sit in a page that isn't shared with the integration
+                Err(Error::NotFound(message)) if synced => {
+                    warn!("Leaving synced block {} empty, its original {} can't be read: {}", block["id"], child_id, message);
+                    Vec::new()
+                },
+                children => children?,
+            };
+            block["children"] = Value::Array(children);
+        }
+    }
+    Ok(())
+}
+
+/// Titles of the pages above each page, root first, for `DECK_HIERARCHY`.
+/// Parents that aren't among `pages` are fetched once each; a path starts
+/// below the first parent that can't be fetched.
+pub async fn page_ancestors(pages: &[NotionPage], config: &Config) -> HashMap<String, Vec<String>> {
+    // Title and parent of every page seen, `None` for pages that couldn't be fetched
+    let mut known: HashMap<String, Option<(String, Option<String>)>> = pages
+        .iter()
+        .map(|page| (page.id.clone(), Some((extract_page_title(page), page.parent_page_id.clone()))))
+        .collect();
+    let mut ancestors = HashMap::new();
+    for page in pages {
+        let mut titles = Vec::new();
+        // A page can't be its own ancestor, but a broken parent chain shouldn't hang the run
+        let mut visited = HashSet::from([page.id.clone()]);
+        let mut parent = page.parent_page_id.clone();
+        while let Some(parent_id) = parent.take() {
+            if !visited.insert(parent_id.clone()) {
+                break;
+            }
+            if !known.contains_key(&parent_id) {
+                debug!("Fetching title of parent page {}", parent_id);
+                let fetched = match fetch_pages_by_id(std::slice::from_ref(&parent_id), config).await {
+                    Ok(found) => found.first().map(|parent| (extract_page_title(parent), parent.parent_page_id.clone())),
+                    Err(error) => {
+                        debug!("Can't fetch parent page {}: {}", parent_id, error);
+                        None
+                    },
+                };
+                known.insert(parent_id.clone(), fetched);
+            }
+            let Some(Some((title, grandparent))) = known.get(&parent_id) else {
+                break;
+            };
+            titles.push(title.clone());
+            parent = grandparent.clone();
+        }
+        titles.reverse();
+        ancestors.insert(page.id.clone(), titles);
+    }
+    ancestors
+}
+
+/// Id and title of every `child_page` block in `blocks` or nested in them,
+/// i.e. the subpages of the page, in document order
+pub fn child_pages(blocks: &[Value]) -> Vec<(String, String)> {
+    let mut subpages = Vec::new();
+    for block in blocks {
+        if block["type"] == "child_page" {
+            if let Some(id) = block["id"].as_str() {
+                let title = block["child_page"]["title"].as_str().unwrap_or("").trim();
+                let title = if title.is_empty() {
+                    format!("Page-{}", id.chars().take(8).collect::<String>())
+                } else {
+                    title.to_string()
+                };
+                subpages.push((id.to_string(), title));
+            }
+        }
+        if let Some(children) = block["children"].as_array() {
+            subpages.extend(child_pages(children));
+        }
+    }
+    subpages
+}
+
+/// Fetch a single block and everything nested in it, as the only top-level block
+pub async fn fetch_block_subtree(block_id: &str, config: &Config) -> Result<Vec<Value>> {
+    let url = format!("{}/blocks/{}", config.notion_api_url, block_id);
+    
+    debug!("Fetching block - Request URL: {}", url);
+    
+    let response = notion_get(&url, config).await?;
+    let status = response.status();
+    let response_text = response.text().await?;
+    
+    debug!("Fetch block response: {}", response_text);
+    
+    if !status.is_success() {
+        return Err(notion_error(&format!("failed to fetch block {}", block_id), status, &response_text));
+    }
+    let mut blocks = vec![serde_json::from_str(&response_text)?];
+    attach_children(&mut blocks, 0, config).await?;
+    info!("Fetched block {}", block_id);
+    Ok(blocks)
+}
+
+async fn fetch_block_children(block_id: &str, config: &Config) -> Result<Vec<Value>> {
+    let mut all_blocks = Vec::new();
+    let mut start_cursor: Option<String> = None;
+    let mut page_number = 1;
+    
+    loop {
+        // Get block children with pagination
+        let mut blocks_url = format!("{}/blocks/{}/children?page_size=100", config.notion_api_url, block_id);
+        if let Some(cursor) = &start_cursor {
+            blocks_url.push_str(&format!("&start_cursor={}", cursor));
+        }
+        
+        debug!("Fetching blocks (batch {}): {}", page_number, blocks_url);
+        
+        let blocks_response = notion_get(&blocks_url, config).await?;
+        let status = blocks_response.status();
+        let response_text = blocks_response.text().await?;
+        if !status.is_success() {
+            return Err(notion_error(&format!("failed to fetch children of block {}", block_id), status, &response_text));
+        }
+        
+        let mut blocks_json: Value = serde_json::from_str(&response_text)?;
+        
+        debug!("blocks_json (batch {}):: {}", page_number, blocks_json);
+        
+        // Accumulate current batch of blocks
+        if let Some(results) = blocks_json["results"].as_array_mut() {
+            all_blocks.append(results);
+        }
+        
+        // Check if there are more pages
+        let has_more = blocks_json["has_more"].as_bool().unwrap_or(false);
+        if !has_more {
+            break;
+        }
+        
+        // Get next page cursor
+        start_cursor = blocks_json["next_cursor"].as_str().map(|s| s.to_string());
+        if start_cursor.is_none() {
+            break;
+        }
+        
+        page_number += 1;
+    }
+    
+    Ok(all_blocks)
+}
@@ -0,0 +1,583 @@
+use std::collections::HashSet;
+use tracing::{debug, warn};
+
+use crate::config::{CardDedupe, CardFormat, Config, LongAnswer};
+use crate::markdown::escape_html;
+use crate::media::PageMedia;
+
+#[derive(Debug, Clone)]
+pub struct Flashcard {
+    pub question: String,
+    pub answer: String,
+    /// Anki fields filled from FIELD_MAP; when set they replace Front/Back
+    pub fields: Vec<(String, String)>,
+    /// Anki media file with the spoken Front, played from a `[sound:...]` tag
+    pub audio: Option<String>,
+    /// Notion block (or database row) the card comes from, see `note_key_tag`
+    pub source_block: Option<String>,
+    /// The question as parsed, once `FRONT_TEMPLATE` rewrote it, see `card_key`
+    pub source_question: Option<String>,
+    /// Anki tags of this card alone, added to the ones of its deck
+    pub tags: Vec<String>,
+}
+
+impl Flashcard {
+    /// Whether the card uses Anki cloze deletions like `{{c1::...}}`
+    pub fn is_cloze(&self) -> bool {
+        contains_cloze(&self.question) || contains_cloze(&self.answer)
+    }
+}
+
+/// Follow every basic card with its swapped (answer → question) copy.
+/// The copies are separate notes, so duplicate detection and updates work
+/// for each direction on their own.
+pub fn add_reversed_cards(flashcards: Vec<Flashcard>) -> Vec<Flashcard> {
+    let mut cards = Vec::with_capacity(flashcards.len() * 2);
+    for flashcard in flashcards {
+        // Mapped fields have no Front and Back to swap
+        let reversed = (!flashcard.is_cloze() && flashcard.fields.is_empty()).then(|| Flashcard {
+            question: flashcard.answer.clone(),
+            answer: flashcard.question.clone(),
+            fields: Vec::new(),
+            audio: None,
+            source_block: flashcard.source_block.as_ref().map(|block| format!("{}:reversed", block)),
+            source_question: None,
+            tags: flashcard.tags.clone(),
+        });
+        cards.push(flashcard);
+        cards.extend(reversed);
+    }
+    cards
+}
+
+/// Drop the cards of `deck_name` that are the same, by `DEDUPE_CARDS`, as a
+/// card kept before, and return how many were dropped. `seen` remembers the
+/// cards kept so far, so repeats are found across the pages of a run.
+pub fn remove_repeated_cards(
+    flashcards: &mut Vec<Flashcard>,
+    deck_name: &str,
+    seen: &mut HashSet<String>,
+    dedupe: CardDedupe,
+) -> usize {
+    if dedupe == CardDedupe::Off {
+        return 0;
+    }
+    let count = flashcards.len();
+    flashcards.retain(|flashcard| {
+        // Unit separators keep "a" + "bc" apart from "ab" + "c"
+        let mut card = format!("{}\u{1f}{}", deck_name, flashcard.question);
+        if dedupe == CardDedupe::FrontBack {
+            card.push('\u{1f}');
+            card.push_str(&flashcard.answer);
+            for (_, value) in &flashcard.fields {
+                card.push('\u{1f}');
+                card.push_str(value);
+            }
+        }
+        seen.insert(card)
+    });
+    count - flashcards.len()
+}
+
+/// Record the top-level block each card's question is on: the last block
+/// starting at or before the question's line
+fn assign_source_blocks(flashcards: &mut [(usize, Flashcard)], block_starts: &[(usize, String)]) {
+    let mut previous_block: Option<&str> = None;
+    let mut cards_in_block = 0;
+    for (question_line, flashcard) in flashcards.iter_mut() {
+        let Some((_, block_id)) = block_starts.iter().rev().find(|(start, _)| start <= question_line) else {
+            continue;
+        };
+        if previous_block == Some(block_id.as_str()) {
+            cards_in_block += 1;
+            flashcard.source_block = Some(format!("{}#{}", block_id, cards_in_block));
+        } else {
+            cards_in_block = 0;
+            flashcard.source_block = Some(block_id.clone());
+        }
+        previous_block = Some(block_id);
+    }
+}
+
+/// Add the comments on a card's block to the end of its answer, one line each
+pub fn append_comments(flashcard: &mut Flashcard, comments: &[String]) {
+    if comments.is_empty() {
+        return;
+    }
+    let lines: Vec<String> = comments.iter().map(|comment| format!("<b>Comment:</b> {}", comment)).collect();
+    // A blank line sets the comments apart from the answer, if there is one
+    if !flashcard.answer.is_empty() {
+        flashcard.answer.push_str("\n\n");
+    }
+    flashcard.answer.push_str(&lines.join("\n"));
+}
+
+/// Rewrite the Front and Back of basic cards with `FRONT_TEMPLATE` and
+/// `BACK_TEMPLATE`. Cloze and mapped cards are left as they are.
+pub fn apply_card_templates(flashcards: &mut [Flashcard], title: &str, media: &PageMedia, config: &Config) {
+    if config.front_template.is_none() && config.back_template.is_none() {
+        return;
+    }
+    let title = escape_html(title);
+    for flashcard in flashcards.iter_mut().filter(|card| !card.is_cloze() && card.fields.is_empty()) {
+        let values = [
+            ("title", title.as_str()),
+            ("icon", media.icon.as_str()),
+            ("cover", media.cover.as_str()),
+            ("front", flashcard.question.as_str()),
+            ("back", flashcard.answer.as_str()),
+        ];
+        let question = config.front_template.as_deref().map(|template| fill_template(template, &values));
+        let answer = config.back_template.as_deref().map(|template| fill_template(template, &values));
+        if let Some(question) = question {
+            let parsed = std::mem::replace(&mut flashcard.question, question);
+            flashcard.source_question.get_or_insert(parsed);
+        }
+        if let Some(answer) = answer {
+            flashcard.answer = answer;
+        }
+    }
+}
+
+/// Replace `{name}` placeholders in one pass, so placeholders inside the
+/// inserted values stay as they are; unknown ones are kept
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            values.iter().find(|(name, _)| *name == &rest[1..end]).map(|(_, value)| (end, *value))
+        });
+        match value {
+            Some((end, value)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn contains_cloze(text: &str) -> bool {
+    text.match_indices("{{c").any(|(index, _)| {
+        let rest = &text[index + 3..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        digits > 0 && rest[digits..].starts_with("::")
+    })
+}
+
+pub fn parse_flashcards_from_markdown(markdown: &str, config: &Config) -> Vec<Flashcard> {
+    parse_flashcards_with_sources(markdown, &[], config)
+}
+
+/// Parse the flashcards of `markdown` and record the top-level block each
+/// card's question is on, given the start line of every block from
+/// `convert_blocks_with_sources`. The parsers tell the line of each question,
+/// so cards are never matched to blocks by their text. The second and later
+/// card of a block get `#n` appended.
+pub fn parse_flashcards_with_sources(markdown: &str, block_starts: &[(usize, String)], config: &Config) -> Vec<Flashcard> {
+    let mut flashcards = match config.card_format {
+        CardFormat::Code => parse_code_block_flashcards(markdown, config),
+        CardFormat::Toggle => parse_toggle_flashcards(markdown),
+        CardFormat::Heading => parse_heading_flashcards(markdown),
+        CardFormat::Cloze => parse_cloze_flashcards(markdown),
+        CardFormat::Inline => parse_inline_flashcards(markdown, &config.inline_delimiter),
+    };
+    // A blank side would make a blank note; cloze cards keep their text in the question
+    flashcards.retain(|(_, card)| !card.question.trim().is_empty() && (card.is_cloze() || !card.answer.trim().is_empty()));
+    if let Some(max_chars) = config.max_answer_chars {
+        limit_answer_length(&mut flashcards, max_chars, config.long_answer);
+    }
+    assign_source_blocks(&mut flashcards, block_starts);
+    
+    debug!("Total parsed flashcards: {}", flashcards.len());
+    
+    flashcards.into_iter().map(|(_, card)| card).collect()
+}
+
+/// Skip or cut off the cards whose answer is over `max_chars` characters,
+/// which is usually a missing marker that let one card run on
+fn limit_answer_length(flashcards: &mut Vec<(usize, Flashcard)>, max_chars: usize, long_answer: LongAnswer) {
+    flashcards.retain_mut(|(_, card)| {
+        let chars = card.answer.chars().count();
+        if chars <= max_chars {
+            return true;
+        }
+        match long_answer {
+            LongAnswer::Skip => {
+                warn!("Skipping \"{}\": its answer has {} characters, over MAX_ANSWER_CHARS", card.question, chars);
+                false
+            },
+            LongAnswer::Truncate => {
+                warn!("Cutting off the answer of \"{}\": it has {} characters, over MAX_ANSWER_CHARS", card.question, chars);
+                card.answer = truncate_html(&card.answer, max_chars);
+                true
+            },
+        }
+    });
+}
+
+/// Elements without a closing tag
+const VOID_ELEMENTS: [&str; 6] = ["br", "hr", "img", "input", "source", "wbr"];
+
+/// Cut `text` down to `max_chars` characters and add an ellipsis. HTML tags and
+/// entities are never split, and the elements left open are closed after the
+/// ellipsis, so an HTML answer stays well-formed.
+fn truncate_html(text: &str, max_chars: usize) -> String {
+    let mut truncated = String::new();
+    let mut open_elements: Vec<String> = Vec::new();
+    let mut chars = 0;
+    let mut rest = text;
+    while let Some(next) = rest.chars().next() {
+        let token = match next {
+            '<' if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') => {
+                rest.find('>').map(|end| &rest[..=end])
+            },
+            '&' => rest
+                .find(';')
+                .map(|end| &rest[..=end])
+                .filter(|entity| entity[1..entity.len() - 1].chars().all(|c| c.is_ascii_alphanumeric() || c == '#')),
+            _ => None,
+        }
+        .unwrap_or(&rest[..next.len_utf8()]);
+        let token_chars = token.chars().count();
+        if chars + token_chars > max_chars {
+            break;
+        }
+        chars += token_chars;
+        rest = &rest[token.len()..];
+        truncated.push_str(token);
+        
+        if let Some(tag) = token.strip_prefix('<').filter(|_| token.len() > 2 && token.ends_with('>')) {
+            let name = tag.trim_start_matches('/').chars().take_while(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase();
+            if tag.starts_with('/') {
+                if let Some(position) = open_elements.iter().rposition(|open| *open == name) {
+                    open_elements.truncate(position);
+                }
+            } else if !tag.ends_with("/>") && !VOID_ELEMENTS.contains(&name.as_str()) {
+                open_elements.push(name);
+            }
+        }
+    }
+    truncated.push('…');
+    for name in open_elements.iter().rev() {
+        truncated.push_str(&format!("</{}>", name));
+    }
+    truncated
+}
+
+/// Text after a line's marker, e.g. `Answer: Paris` gives `Paris`. Markers may be
+/// configured with or without their colon; either colon is accepted after them.
+fn strip_marker<'a>(line: &'a str, markers: &[String]) -> Option<&'a str> {
+    markers.iter().find_map(|marker| {
+        let marker = marker.trim().trim_end_matches([':', '：']);
+        if marker.is_empty() {
+            return None;
+        }
+        let rest = line.strip_prefix(marker)?;
+        rest.strip_prefix(':').or_else(|| rest.strip_prefix('：')).map(str::trim)
+    })
+}
+
+/// Parse `问题:`/`答案:` flashcards from inside fenced code blocks. A
+/// `CARD_SEPARATOR` line, in or outside a code block, ends the current card.
+fn parse_code_block_flashcards(markdown: &str, config: &Config) -> Vec<(usize, Flashcard)> {
+    let separator = config.card_separator.trim();
+    let mut flashcards = Vec::new();
+    let mut in_code_block = false;
+    let mut current_question = None;
+    let mut current_answer = String::new();
+    let mut current_code_block = String::new();
+    let mut code_block_count = 0;
+    
+    for (index, line) in markdown.lines().enumerate() {
+        let line = line.trim();
+        
+        if !separator.is_empty() && line == separator {
+            if let Some((question_line, question)) = current_question.take() {
+                if !current_answer.is_empty() {
+                    flashcards.push((question_line, Flashcard {
+                        question,
+                        answer: current_answer.trim().to_string(),
+                        fields: Vec::new(),
+                        audio: None,
+                        source_block: None,
+                        source_question: None,
+                        tags: Vec::new(),
+                    }));
+                }
+            }
+            current_answer.clear();
+            continue;
+        }
+        
+        // Detect code blocks
+        if line.starts_with("```") {
+            if in_code_block {
+                // End of code block - print it
+                code_block_count += 1;
+                debug!("Code block {}:\n{}", code_block_count, current_code_block);
+                current_code_block.clear();
+            }
+            in_code_block = !in_code_block;
+            continue;
+        }
+        
+        // Collect code block content
+        if in_code_block {
+            current_code_block.push_str(line);
+            current_code_block.push('\n');
+        }
+        
+        // Only process lines inside code blocks
+        if !in_code_block {
+            continue;
+        }
+        
+        if let Some(question_text) = strip_marker(line, &config.question_markers) {
+            // Save previous flashcard if exists
+            if let Some((question_line, question)) = current_question.take() {
+                if !current_answer.is_empty() {
+                    flashcards.push((question_line, Flashcard {
+                        question,
+                        answer: current_answer.trim().to_string(),
+                        fields: Vec::new(),
+                        audio: None,
+                        source_block: None,
+                        source_question: None,
+                        tags: Vec::new(),
+                    }));
+                    current_answer.clear();
+                }
+            }
+            // Start new question
+            current_question = Some((index, question_text.to_string()));
+        } else if let Some(answer_text) = strip_marker(line, &config.answer_markers) {
+            if current_question.is_some() {
+                // Separators are only added between lines, so an answer that
+                // continues on the next line doesn't gain a blank line
+                if !answer_text.is_empty() {
+                    if !current_answer.is_empty() {
+                        current_answer.push('\n');
+                    }
+                    current_answer.push_str(answer_text);
+                }
+            }
+        } else if current_question.is_some() && !line.is_empty() {
+            if !current_answer.is_empty() {
+                current_answer.push('\n');
+            }
+            current_answer.push_str(line);
+        }
+    }
+    
+    // Add last flashcard
+    if let Some((question_line, question)) = current_question {
+        if !current_answer.is_empty() {
+            flashcards.push((question_line, Flashcard {
+                question,
+                answer: current_answer.trim().to_string(),
+                fields: Vec::new(),
+                audio: None,
+                source_block: None,
+                source_question: None,
+                tags: Vec::new(),
+            }));
+        }
+    }
+    
+    flashcards
+}
+
+/// Parse flashcards from top-level toggles: the `<summary>` is the question and
+/// everything up to the matching `</details>` (nested toggles included) is the answer
+fn parse_toggle_flashcards(markdown: &str) -> Vec<(usize, Flashcard)> {
+    let mut flashcards = Vec::new();
+    let mut depth: usize = 0;
+    let mut current_question: Option<(usize, String)> = None;
+    let mut current_answer = String::new();
+    
+    for (index, raw_line) in markdown.lines().enumerate() {
+        let line = raw_line.trim();
+        
+        if line == "<details>" {
+            depth += 1;
+            if depth == 1 {
+                continue;
+            }
+        } else if line == "</details>" {
+            depth = depth.saturating_sub(1);
+            if depth == 0 {
+                if let Some((question_line, question)) = current_question.take() {
+                    if !current_answer.trim().is_empty() {
+                        flashcards.push((question_line, Flashcard {
+                            question,
+                            answer: current_answer.trim().to_string(),
+                            fields: Vec::new(),
+                            audio: None,
+                            source_block: None,
+                            source_question: None,
+                            tags: Vec::new(),
+                        }));
+                    }
+                }
+                current_answer.clear();
+                continue;
+            }
+        } else if depth == 1 && current_question.is_none() {
+            if let Some(summary) = line.strip_prefix("<summary>").and_then(|l| l.strip_suffix("</summary>")) {
+                current_question = Some((index, summary.trim().to_string()));
+                continue;
+            }
+        }
+        
+        // Only lines inside a toggle belong to an answer
+        if depth == 0 || line.is_empty() {
+            continue;
+        }
+        if !current_answer.is_empty() {
+            current_answer.push('\n');
+        }
+        // Leading spaces are the nesting of list items
+        current_answer.push_str(raw_line.trim_end());
+    }
+    
+    flashcards
+}
+
+/// Parse flashcards from headings: each `#`, `##` or `###` heading is a question
+/// and the non-empty lines up to the next heading are its answer
+fn parse_heading_flashcards(markdown: &str) -> Vec<(usize, Flashcard)> {
+    let mut flashcards = Vec::new();
+    let mut current_question: Option<(usize, String)> = None;
+    let mut current_answer = String::new();
+    let mut in_code_block = false;
+    
+    for (index, raw_line) in markdown.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        
+        // A `# comment` in a code block isn't a heading
+        let heading = ["### ", "## ", "# "]
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix))
+            .filter(|_| !in_code_block);
+        if let Some(heading) = heading {
+            if let Some((question_line, question)) = current_question.take() {
+                if !current_answer.is_empty() {
+                    flashcards.push((question_line, Flashcard {
+                        question,
+                        answer: current_answer.clone(),
+                        fields: Vec::new(),
+                        audio: None,
+                        source_block: None,
+                        source_question: None,
+                        tags: Vec::new(),
+                    }));
+                }
+            }
+            current_answer.clear();
+            current_question = Some((index, heading.trim().to_string()));
+            continue;
+        }
+        
+        // Content before the first heading has no question to belong to
+        if current_question.is_none() || (line.is_empty() && !in_code_block) {
+            continue;
+        }
+        if !current_answer.is_empty() {
+            current_answer.push('\n');
+        }
+        current_answer.push_str(raw_line.trim_end());
+    }
+    
+    if let Some((question_line, question)) = current_question {
+        if !current_answer.is_empty() {
+            flashcards.push((question_line, Flashcard {
+                question,
+                answer: current_answer,
+                fields: Vec::new(),
+                audio: None,
+                source_block: None,
+                source_question: None,
+                tags: Vec::new(),
+            }));
+        }
+    }
+    
+    flashcards
+}
+
+/// Parse cloze cards: every line containing `{{cN::...}}` becomes one card
+/// whose text is the whole line
+fn parse_cloze_flashcards(markdown: &str) -> Vec<(usize, Flashcard)> {
+    markdown
+        .lines()
+        .map(|line| line.trim())
+        .enumerate()
+        .filter(|(_, line)| contains_cloze(line))
+        .map(|(index, line)| (index, Flashcard {
+            question: line.to_string(),
+            answer: String::new(),
+            fields: Vec::new(),
+            audio: None,
+            source_block: None,
+            source_question: None,
+            tags: Vec::new(),
+        }))
+        .collect()
+}
+
+/// Split every line at the first `delimiter` into a question and answer.
+/// Cloze lines are cloze cards, since `::` is part of their deletions.
+fn parse_inline_flashcards(markdown: &str, delimiter: &str) -> Vec<(usize, Flashcard)> {
+    let mut flashcards = Vec::new();
+    for (index, line) in markdown.lines().map(strip_list_marker).enumerate().filter(|(_, line)| !line.is_empty()) {
+        let (question, answer) = if contains_cloze(line) {
+            (line, "")
+        } else if let Some((question, answer)) = line.split_once(delimiter) {
+            (question.trim(), answer.trim())
+        } else {
+            debug!("Skipping line without \"{}\": {}", delimiter, line);
+            continue;
+        };
+        flashcards.push((index, Flashcard {
+            question: question.to_string(),
+            answer: answer.to_string(),
+            fields: Vec::new(),
+            audio: None,
+            source_block: None,
+            source_question: None,
+            tags: Vec::new(),
+        }));
+    }
+    flashcards
+}
+
+/// A line without the `- ` or `1. ` of the list item it is in, and without
+/// the `[ ]` or `[x]` checkbox of a to-do item
+fn strip_list_marker(line: &str) -> &str {
+    let line = line.trim();
+    if let Some(item) = line.strip_prefix("- ") {
+        let item = item.trim_start();
+        return ["[ ] ", "[x] "]
+            .iter()
+            .find_map(|checkbox| item.strip_prefix(checkbox))
+            .unwrap_or(item)
+            .trim_start();
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    match line[digits..].strip_prefix(". ") {
+        Some(item) if digits > 0 => item.trim_start(),
+        _ => line,
+    }
+}
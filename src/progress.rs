@@ -0,0 +1,62 @@
+use std::io::{self, IsTerminal, Write};
+
+use crate::config::Config;
+
+const BAR_WIDTH: usize = 30;
+
+/// Progress line for the page loop, drawn on stderr while the next page is
+/// fetched and cleared before that page prints its own output
+#[derive(Debug)]
+pub struct Progress {
+    total: usize,
+    done: usize,
+    enabled: bool,
+}
+
+impl Progress {
+    /// A bar over `total` pages, with none of them processed yet
+    pub fn new(total: usize, config: &Config) -> Self {
+        let enabled = Progress::enabled(config, io::stderr().is_terminal());
+        Progress { total, done: 0, enabled }
+    }
+
+    /// Only shown in a terminal, and not next to debug or `RUST_LOG` logs
+    pub fn enabled(config: &Config, terminal: bool) -> bool {
+        config.progress && !config.debug_mode && config.log_filter.is_none() && terminal
+    }
+
+    /// The bar with the pages processed so far
+    pub fn line(&self, added: usize) -> String {
+        let filled = BAR_WIDTH * self.done / self.total.max(1);
+        format!(
+            "[{}{}] {}/{} pages, {} cards added",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            added,
+        )
+    }
+
+    /// Draw the bar over the one drawn before
+    pub fn draw(&self, added: usize) {
+        if !self.enabled || self.total == 0 {
+            return;
+        }
+        eprint!("\r\x1b[K{}", self.line(added));
+        io::stderr().flush().ok();
+    }
+
+    /// Clear the bar and count the next page as processed
+    pub fn next_page(&mut self) {
+        self.done += 1;
+        self.clear();
+    }
+
+    pub fn clear(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[K");
+            io::stderr().flush().ok();
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::notion::normalize_page_id;
+use crate::parser::Flashcard;
+
+/// What is remembered between runs, stored as JSON in the state file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Start time of the last successful import, in RFC 3339
+    #[serde(default)]
+    pub last_sync: Option<String>,
+    /// Anki note id of every imported card, keyed by `card_key`
+    #[serde(default)]
+    pub notes: BTreeMap<String, u64>,
+}
+
+impl SyncState {
+    /// Load the state file; a missing file means nothing was imported yet
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(SyncState::default());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("Failed to read state file {}: {}", path, e)))?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::Config(format!("Failed to parse state file {}: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content)
+            .map_err(|e| Error::Config(format!("Failed to write state file {}: {}", path, e)))
+    }
+
+    /// Whether a page edited at `last_edited_time` may have changed since the
+    /// last sync. Notion rounds edit times down to the minute, so a page edited
+    /// within the minute before the sync started still counts as changed.
+    pub fn is_changed(&self, last_edited_time: &str) -> bool {
+        match self.last_sync.as_deref() {
+            Some(last_sync) => edited_since(last_edited_time, last_sync),
+            None => true,
+        }
+    }
+}
+
+/// Whether an edit at the minute-rounded `last_edited_time` may have happened
+/// after `timestamp`; unparsable times count as edited
+pub fn edited_since(last_edited_time: &str, timestamp: &str) -> bool {
+    match (parse_time(last_edited_time), parse_time(timestamp)) {
+        (Some(edited), Some(since)) => edited + Duration::minutes(1) > since,
+        _ => true,
+    }
+}
+
+/// Key identifying a card across runs: the page (or database) it comes
+/// from and a hash of its question, so reordering cards keeps their notes.
+/// The question is the one in Notion, so editing `FRONT_TEMPLATE` or renaming
+/// the page doesn't change the keys.
+pub fn card_key(source_id: &str, flashcard: &Flashcard) -> String {
+    let question = flashcard.source_question.as_deref().unwrap_or(&flashcard.question);
+    format!("{}:{:016x}", source_id, stable_hash(question))
+}
+
+/// Start of the tag that holds a note's key
+pub const NOTE_KEY_TAG_PREFIX: &str = "n2a:";
+
+/// Tag identifying a card's note by where the card is rather than by its
+/// text, so a note is found again after its Front was edited: `n2a:` and a
+/// hash of the page (or database) id and the block (or row) id. Cards whose
+/// block isn't known have no key.
+pub fn note_key_tag(source_id: &str, flashcard: &Flashcard) -> Option<String> {
+    let block = flashcard.source_block.as_deref()?;
+    Some(format!("{}{:016x}", NOTE_KEY_TAG_PREFIX, stable_hash(&format!("{}:{}", source_id, block))))
+}
+
+/// Source id of the cards from a database. The prefix keeps them apart from
+/// page cards, which a full page sync prunes when the page is gone.
+pub fn database_source_id(database_id: &str) -> String {
+    format!("database-{}", database_id)
+}
+
+/// Whether a card key's source is a page rather than a database
+pub fn is_page_source(source_id: &str) -> bool {
+    normalize_page_id(source_id).is_some()
+}
+
+/// FNV-1a, which unlike the std hasher gives the same value on every run
+pub fn stable_hash(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Current time in the format Notion uses for timestamps
+pub fn now_timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Timestamp for `EDITED_SINCE`, given as a date (midnight UTC) or an RFC 3339 time
+pub fn since_timestamp(since: &str) -> Option<String> {
+    let since = since.trim();
+    let time = match NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        Ok(date) => date.and_hms_opt(0, 0, 0)?.and_utc(),
+        Err(_) => parse_time(since)?,
+    };
+    Some(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+fn parse_time(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
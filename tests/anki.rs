@@ -0,0 +1,13 @@
+use notion2anki::anki::clear_deck_query;
+
+#[test]
+fn clearing_a_deck_leaves_its_subdecks_alone() {
+    assert_eq!(clear_deck_query("Biology"), "\"deck:Biology\" -\"deck:Biology::*\"");
+}
+
+#[test]
+fn clearing_a_deck_matches_its_name_literally() {
+    assert_eq!(clear_deck_query("C*"), "\"deck:C\\*\" -\"deck:C\\*::*\"");
+    assert_eq!(clear_deck_query("Week_1"), "\"deck:Week\\_1\" -\"deck:Week\\_1::*\"");
+    assert_eq!(clear_deck_query("\"Quotes\""), "\"deck:\\\"Quotes\\\"\" -\"deck:\\\"Quotes\\\"::*\"");
+}
@@ -0,0 +1,51 @@
+use notion2anki::cache::{load_cached_blocks, store_cached_blocks};
+use notion2anki::NotionPage;
+use serde_json::{json, Value};
+
+fn page(last_edited_time: &str) -> NotionPage {
+    serde_json::from_value(json!({
+        "id": "1f2e3d4c-5b6a-7980-a1b2-c3d4e5f60718",
+        "last_edited_time": last_edited_time,
+        "properties": {},
+    }))
+    .unwrap()
+}
+
+fn cache_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("notion2anki-cache-test-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir.to_string_lossy().into_owned()
+}
+
+#[test]
+fn cached_blocks_are_used_until_the_page_is_edited() {
+    let dir = cache_dir("edited");
+    let blocks = vec![json!({ "type": "paragraph", "paragraph": { "rich_text": [] } })];
+    store_cached_blocks(&dir, &page("2024-03-01T12:00:00.000Z"), "2024-03-01T12:05:00.000Z".to_string(), &blocks);
+    
+    assert_eq!(load_cached_blocks(&dir, &page("2024-03-01T12:00:00.000Z")), Some(blocks));
+    assert_eq!(load_cached_blocks(&dir, &page("2024-03-01T12:10:00.000Z")), None);
+}
+
+#[test]
+fn blocks_fetched_within_the_edit_minute_are_outdated() {
+    let dir = cache_dir("minute");
+    let blocks = vec![json!({ "type": "divider", "divider": {} })];
+    // An edit at 12:00:50 would still show as 12:00
+    store_cached_blocks(&dir, &page("2024-03-01T12:00:00.000Z"), "2024-03-01T12:00:30.000Z".to_string(), &blocks);
+    
+    assert_eq!(load_cached_blocks(&dir, &page("2024-03-01T12:00:00.000Z")), None);
+}
+
+#[test]
+fn pages_with_notion_hosted_files_are_not_cached() {
+    let dir = cache_dir("files");
+    let image: Value = json!({
+        "type": "image",
+        "image": { "type": "file", "file": { "url": "https://example.com/a.png?signature" } },
+    });
+    let blocks = vec![json!({ "type": "toggle", "toggle": { "rich_text": [] }, "children": [image] })];
+    store_cached_blocks(&dir, &page("2024-03-01T12:00:00.000Z"), "2024-03-01T12:05:00.000Z".to_string(), &blocks);
+    
+    assert_eq!(load_cached_blocks(&dir, &page("2024-03-01T12:00:00.000Z")), None);
+}
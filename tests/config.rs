@@ -0,0 +1,94 @@
+use clap::Parser;
+use notion2anki::{Args, Command, Config};
+
+#[test]
+fn decks_are_only_cleared_when_asked_to() {
+    assert!(!Config::default().clears_decks(0));
+    
+    let config = Config {
+        clear_deck: true,
+        ..Config::default()
+    };
+    assert!(config.clears_decks(0));
+    // A deck per page only holds the cards of that page
+    assert!(config.clears_decks(3));
+    assert!(Config { page_ids: vec!["page".to_string()], ..config.clone() }.clears_decks(0));
+    
+    let block = Config {
+        block_url: Some("https://www.notion.so/Page-1f2e3d4c5b6a7980a1b2c3d4e5f60718#0a1b2c3d4e5f60718293a4b5c6d7e8f9".to_string()),
+        ..config.clone()
+    };
+    assert!(!block.clears_decks(0));
+    // The limit may leave a cleared deck with only some of its cards
+    assert!(!Config { card_limit: Some(10), ..config }.clears_decks(0));
+}
+
+#[test]
+fn shared_decks_are_only_cleared_when_every_page_is_imported() {
+    let shared = Config {
+        clear_deck: true,
+        deck_per_page: false,
+        deck_name: Some("Notion".to_string()),
+        ..Config::default()
+    };
+    assert!(shared.clears_decks(0));
+    
+    assert!(!shared.clears_decks(3));
+    assert!(!Config { since: Some("2024-01-01".to_string()), ..shared.clone() }.clears_decks(0));
+    assert!(!Config { page_ids: vec!["page".to_string()], ..shared.clone() }.clears_decks(0));
+    assert!(!Config { page_title_filter: Some("Biology".to_string()), ..shared.clone() }.clears_decks(0));
+    
+    // Pages can share the decks their headings name
+    let headings = Config {
+        clear_deck: true,
+        deck_headings: true,
+        page_ids: vec!["page".to_string()],
+        ..Config::default()
+    };
+    assert!(!headings.clears_decks(0));
+}
+
+#[test]
+fn clearing_decks_and_updating_notes_exclude_each_other() {
+    let config = Config {
+        notion_api_key: "secret_test".to_string(),
+        clear_deck: true,
+        update_mode: true,
+        ..Config::default()
+    };
+    let error = config.validate().unwrap_err().to_string();
+    assert!(error.contains("CLEAR_DECK"), "{}", error);
+}
+
+#[test]
+fn debug_output_leaves_out_the_keys() {
+    let config = Config {
+        notion_api_key: "secret_1234".to_string(),
+        anki_connect_api_key: Some("anki-5678".to_string()),
+        deck_name: Some("Biology".to_string()),
+        ..Config::default()
+    };
+    let debug = format!("{:?}", config);
+    assert!(!debug.contains("secret_1234") && !debug.contains("anki-5678"), "{}", debug);
+    assert!(debug.contains("notion_api_key: <redacted>"), "{}", debug);
+    assert!(debug.contains("deck_name: \"Biology\""), "{}", debug);
+}
+
+#[test]
+fn note_types_can_be_set_in_the_config_file() {
+    let path = std::env::temp_dir().join(format!("notion2anki-models-test-{}.toml", std::process::id()));
+    std::fs::write(&path, "model_name = \"My Basic\"\ncloze_model_name = \"My Cloze\"\n").unwrap();
+    
+    let config = Config::load_from_file(&path.to_string_lossy()).unwrap();
+    assert_eq!(config.model_name, "My Basic");
+    assert_eq!(config.cloze_model_name, "My Cloze");
+    assert_eq!(Config::default().model_name, "Basic");
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn the_key_is_stored_with_a_command() {
+    let args = Args::try_parse_from(["notion2anki", "store-token"]).unwrap();
+    assert!(matches!(args.command, Some(Command::StoreToken)));
+    assert!(Args::try_parse_from(["notion2anki", "--store-token"]).is_err());
+}
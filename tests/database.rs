@@ -0,0 +1,27 @@
+use notion2anki::database::database_row_flashcards;
+use notion2anki::{Config, NotionPage};
+use serde_json::json;
+
+#[test]
+fn rows_are_tagged_with_their_multi_select_options() {
+    let row: NotionPage = serde_json::from_value(json!({
+        "id": "row-1",
+        "last_edited_time": "2024-03-01T12:00:00.000Z",
+        "properties": {
+            "Question": { "type": "title", "title": [{ "plain_text": "Capital of France?" }] },
+            "Answer": { "type": "rich_text", "rich_text": [{ "plain_text": "Paris" }] },
+            "Tags": { "type": "multi_select", "multi_select": [{ "name": "geography" }, { "name": "Western Europe" }] },
+        },
+    }))
+    .unwrap();
+    let config = Config {
+        tags_property: Some("Tags".to_string()),
+        ..Config::default()
+    };
+    
+    let rows = [row];
+    let flashcards = database_row_flashcards(&rows, &[], &config);
+    assert_eq!(flashcards[0].tags, vec!["geography".to_string(), "Western-Europe".to_string()]);
+    // Without the setting the property is just another column
+    assert!(database_row_flashcards(&rows, &[], &Config::default())[0].tags.is_empty());
+}
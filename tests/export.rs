@@ -0,0 +1,77 @@
+use notion2anki::export::{write_apkg, write_csv, CardLog, CardRecord, CardStatus};
+use notion2anki::parser::Flashcard;
+use notion2anki::DeckCards;
+
+fn card(question: &str, answer: &str) -> Flashcard {
+    Flashcard {
+        question: question.to_string(),
+        answer: answer.to_string(),
+        fields: Vec::new(),
+        audio: None,
+        source_block: None,
+        source_question: None,
+        tags: Vec::new(),
+    }
+}
+
+#[test]
+fn writes_basic_and_cloze_cards_to_a_package() {
+    let decks = vec![DeckCards {
+        source_id: "page".to_string(),
+        title: "Page".to_string(),
+        source_url: "https://notion.so/page".to_string(),
+        deck_name: "Notion::Page".to_string(),
+        tags: vec!["notion".to_string()],
+        flashcards: vec![card("Q", "A"), card("The {{c1::answer}}", "")],
+    }];
+    let path = std::env::temp_dir().join("notion2anki-export-test.apkg");
+    
+    write_apkg(path.to_str().unwrap(), &decks).unwrap();
+    
+    assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn quotes_csv_fields_that_need_it() {
+    let decks = vec![DeckCards {
+        source_id: "page".to_string(),
+        title: "Page".to_string(),
+        source_url: "https://notion.so/page".to_string(),
+        deck_name: "Notion".to_string(),
+        tags: vec!["a".to_string(), "b".to_string()],
+        flashcards: vec![card("Why, \"really\"?", "Line one\nline two")],
+    }];
+    let path = std::env::temp_dir().join("notion2anki-export-test.csv");
+    
+    write_csv(path.to_str().unwrap(), &decks, ',').unwrap();
+    
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert!(csv.starts_with("#separator:Comma\n"));
+    assert!(csv.ends_with("\"Why, \"\"really\"\"?\",Line one<br>line two,Notion,a b,Basic\n"), "{}", csv);
+}
+
+#[test]
+fn card_log_writes_a_json_line_per_card() {
+    let path = std::env::temp_dir().join("notion2anki-export-test.ndjson");
+    let mut log = CardLog::create(path.to_str()).unwrap();
+    
+    let added = CardRecord { page_id: "page", deck: "Notion", front: "Q", back: "A", anki_note_id: Some(7), status: CardStatus::Added, reason: None };
+    log.record(&added).unwrap();
+    let failed = CardRecord { anki_note_id: None, status: CardStatus::Failed, reason: Some("empty"), ..added };
+    log.record(&failed).unwrap();
+    
+    let lines = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(
+        lines,
+        concat!(
+            "{\"page_id\":\"page\",\"deck\":\"Notion\",\"front\":\"Q\",\"back\":\"A\",\"anki_note_id\":7,\"status\":\"added\"}\n",
+            "{\"page_id\":\"page\",\"deck\":\"Notion\",\"front\":\"Q\",\"back\":\"A\",\"anki_note_id\":null,\"status\":\"failed\",\"reason\":\"empty\"}\n",
+        )
+    );
+    
+    // Without a path nothing is written
+    CardLog::create(None).unwrap().record(&added).unwrap();
+}
@@ -0,0 +1,56 @@
+use notion2anki::{run, CardFormat, Config};
+use serde_json::json;
+
+#[tokio::test]
+async fn dumped_blocks_are_imported_without_notion() {
+    let dir = std::env::temp_dir().join(format!("notion2anki-input-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("Geography.json");
+    let blocks = json!([{
+        "id": "block-a",
+        "type": "toggle",
+        "toggle": { "rich_text": [{ "plain_text": "Capital of France?" }] },
+        "children": [{ "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": "Paris" }] } }],
+    }]);
+    std::fs::write(&input_path, blocks.to_string()).unwrap();
+    
+    let config = Config {
+        input_path: Some(input_path.to_string_lossy().into_owned()),
+        card_format: CardFormat::Toggle,
+        dry_run: true,
+        ..Config::default()
+    };
+    config.validate().unwrap();
+    let summary = run(&config).await.unwrap();
+    
+    assert_eq!(summary.added, 1);
+    assert_eq!(summary.page_reports[0].title, "Geography");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn dumped_pages_keep_their_title() {
+    let dir = std::env::temp_dir().join(format!("notion2anki-input-page-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let page_id = "1f2e3d4c-5b6a-7980-a1b2-c3d4e5f60718";
+    let input_path = dir.join(format!("{}.json", page_id));
+    std::fs::write(&input_path, "[{ \"type\": \"heading_1\", \"heading_1\": { \"rich_text\": [{ \"plain_text\": \"Ser?\" }] } }, { \"type\": \"paragraph\", \"paragraph\": { \"rich_text\": [{ \"plain_text\": \"to be\" }] } }]").unwrap();
+    let page = json!({
+        "id": page_id,
+        "last_edited_time": "2024-03-01T12:00:00.000Z",
+        "properties": { "Name": { "type": "title", "title": [{ "plain_text": "Spanish" }] } },
+    });
+    std::fs::write(dir.join(format!("{}.page.json", page_id)), page.to_string()).unwrap();
+    
+    let config = Config {
+        input_path: Some(input_path.to_string_lossy().into_owned()),
+        card_format: CardFormat::Heading,
+        dry_run: true,
+        ..Config::default()
+    };
+    let summary = run(&config).await.unwrap();
+    
+    assert_eq!(summary.added, 1);
+    assert_eq!(summary.page_reports[0].title, "Spanish");
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,321 @@
+use notion2anki::markdown::skipped_block_types;
+use notion2anki::{convert_blocks_to_markdown, CardFormat, Config, Summary, TextFormat};
+use serde_json::{json, Value};
+
+fn bullet(text: &str, children: Vec<Value>) -> Value {
+    json!({
+        "type": "bulleted_list_item",
+        "bulleted_list_item": { "rich_text": [{ "plain_text": text }] },
+        "children": children,
+    })
+}
+
+#[test]
+fn nested_list_items_are_indented_two_spaces_per_level() {
+    let blocks = vec![
+        bullet("Fruit", vec![bullet("Apple", vec![bullet("Fuji", vec![])]), bullet("Pear", vec![])]),
+        bullet("Vegetables", vec![]),
+    ];
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &Config::default()),
+        "- Fruit\n  - Apple\n    - Fuji\n  - Pear\n- Vegetables\n"
+    );
+}
+
+#[test]
+fn paragraphs_in_list_items_continue_the_item() {
+    let paragraph = |text: &str| json!({ "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": text }] } });
+    let blocks = vec![
+        bullet("Mitochondria", vec![paragraph("They make ATP.\nMostly."), bullet("Cristae", vec![paragraph("Folds")])]),
+        bullet("Ribosomes", vec![]),
+        paragraph("After the list"),
+    ];
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &Config::default()),
+        "- Mitochondria\n  They make ATP.\n  Mostly.\n  - Cristae\n    Folds\n- Ribosomes\nAfter the list\n"
+    );
+}
+
+#[test]
+fn html_code_blocks_keep_their_language_as_class() {
+    let blocks = vec![json!({
+        "type": "code",
+        "code": { "language": "rust", "rich_text": [{ "plain_text": "if a < b {\n    swap();\n}" }] },
+    })];
+    let config = Config {
+        text_format: TextFormat::Html,
+        card_format: CardFormat::Heading,
+        ..Config::default()
+    };
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &config),
+        "<pre><code class=\"language-rust\">if a &lt; b {<br>    swap();<br>}</code></pre>\n"
+    );
+}
+
+#[test]
+fn plain_text_is_html_escaped() {
+    let blocks = vec![json!({
+        "type": "paragraph",
+        "paragraph": { "rich_text": [{ "plain_text": "a < b && b > c" }] },
+    })];
+    assert_eq!(convert_blocks_to_markdown(&blocks, &Config::default()), "a &lt; b &amp;&amp; b &gt; c\n");
+}
+
+#[test]
+fn date_mentions_show_their_date() {
+    let blocks = vec![json!({
+        "type": "paragraph",
+        "paragraph": { "rich_text": [
+            { "plain_text": "Due " },
+            {
+                "type": "mention",
+                "plain_text": "@Tomorrow",
+                "mention": { "type": "date", "date": { "start": "2024-03-01", "end": "2024-03-02T09:30:00.000+01:00" } },
+            },
+        ] },
+    })];
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &Config::default()),
+        "Due 2024-03-01 → 2024-03-02 09:30\n"
+    );
+}
+
+fn numbered(text: &str) -> Value {
+    json!({
+        "type": "numbered_list_item",
+        "numbered_list_item": { "rich_text": [{ "plain_text": text }] },
+    })
+}
+
+#[test]
+fn numbered_lists_count_up_and_restart_after_other_blocks() {
+    let blocks = vec![numbered("a"), numbered("b"), bullet("x", vec![]), numbered("c")];
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &Config::default()),
+        "1. a\n2. b\n- x\n1. c\n"
+    );
+    
+    let config = Config {
+        text_format: TextFormat::Html,
+        ..Config::default()
+    };
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks[..2], &config),
+        "<ol>\n<li>a\n</li>\n<li>b\n</li>\n</ol>\n"
+    );
+}
+
+#[test]
+fn blank_numbered_items_are_left_out() {
+    let blocks = vec![numbered("a"), numbered(" "), numbered("b")];
+    assert_eq!(convert_blocks_to_markdown(&blocks, &Config::default()), "1. a\n2. b\n");
+    
+    let config = Config {
+        text_format: TextFormat::Html,
+        ..Config::default()
+    };
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks[1..], &config),
+        "<ol>\n<li>b\n</li>\n</ol>\n"
+    );
+}
+
+#[test]
+fn columns_are_flattened_left_to_right() {
+    let column = |text: &str| json!({ "type": "column", "column": {}, "children": [bullet(text, vec![])] });
+    let blocks = vec![json!({
+        "type": "column_list",
+        "column_list": {},
+        "children": [column("Left"), column("Right")],
+    })];
+    assert_eq!(convert_blocks_to_markdown(&blocks, &Config::default()), "- Left\n- Right\n");
+}
+
+#[test]
+fn blocks_without_text_are_left_out() {
+    let empty = |block_type: &str| json!({ "type": block_type, block_type: { "rich_text": [] } });
+    let blocks = vec![
+        empty("heading_1"),
+        empty("paragraph"),
+        json!({ "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": "  " }] } }),
+        json!({ "type": "divider", "divider": {} }),
+        empty("quote"),
+    ];
+    let config = Config {
+        card_format: CardFormat::Heading,
+        ..Config::default()
+    };
+    assert_eq!(convert_blocks_to_markdown(&blocks, &config), "<hr>\n");
+}
+
+#[test]
+fn html_colors_become_styled_spans() {
+    let blocks = vec![json!({
+        "type": "paragraph",
+        "paragraph": { "rich_text": [
+            { "plain_text": "old", "annotations": { "strikethrough": true, "color": "red" } },
+            { "plain_text": " new", "annotations": { "bold": true, "color": "yellow_background" } },
+            { "plain_text": " plain", "annotations": { "color": "mauve" } },
+        ] },
+    })];
+    let config = Config {
+        text_format: TextFormat::Html,
+        ..Config::default()
+    };
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &config),
+        "<span style=\"color:#d44c47\"><s>old</s></span><span style=\"background-color:#fbf3db\"><b> new</b></span> plain\n"
+    );
+}
+
+#[test]
+fn deck_headings_split_the_page_into_sections() {
+    let heading = |text: &str| json!({ "type": "heading_1", "heading_1": { "rich_text": [{ "plain_text": text }] } });
+    let blocks = vec![
+        bullet("Intro", vec![]),
+        heading("Spanish::Verbs"),
+        bullet("Ser", vec![]),
+        bullet("Estar", vec![]),
+        heading(" "),
+        bullet("Outro", vec![]),
+    ];
+    let sections = notion2anki::markdown::split_deck_sections(&blocks);
+    let decks: Vec<(Option<&str>, usize)> = sections.iter().map(|(deck, blocks)| (deck.as_deref(), blocks.len())).collect();
+    assert_eq!(decks, vec![(None, 1), (Some("Spanish::Verbs"), 2), (None, 1)]);
+}
+
+#[test]
+fn blocks_without_content_leave_no_blank_lines() {
+    let blocks = vec![
+        bullet("Before", vec![]),
+        json!({ "type": "table_of_contents", "table_of_contents": {} }),
+        json!({ "type": "unsupported", "unsupported": {} }),
+        bullet("After", vec![]),
+    ];
+    assert_eq!(convert_blocks_to_markdown(&blocks, &Config::default()), "- Before\n- After\n");
+}
+
+#[test]
+fn link_blocks_show_their_caption_or_url() {
+    let blocks = vec![
+        json!({
+            "type": "bookmark",
+            "bookmark": { "url": "https://example.com/a?b=1&c=2", "caption": [{ "plain_text": "Docs" }] },
+        }),
+        json!({
+            "type": "video",
+            "video": { "type": "external", "external": { "url": "https://youtu.be/xyz" }, "caption": [] },
+        }),
+    ];
+    let config = Config {
+        text_format: TextFormat::Html,
+        ..Config::default()
+    };
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &config),
+        "<a href=\"https://example.com/a?b=1&amp;c=2\">Docs</a>\n\n<a href=\"https://youtu.be/xyz\">https://youtu.be/xyz</a>\n"
+    );
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks[1..], &Config::default()),
+        "[https://youtu.be/xyz](https://youtu.be/xyz)\n"
+    );
+}
+
+#[test]
+fn blocks_are_separated_by_at_most_one_blank_line() {
+    let paragraph = |text: &str| json!({ "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": text }] } });
+    let blocks = vec![
+        json!({ "type": "audio", "audio": {} }),
+        paragraph("One"),
+        json!({ "type": "pdf", "pdf": {} }),
+        json!({ "type": "pdf", "pdf": {} }),
+        paragraph("Two"),
+        json!({ "type": "code", "code": { "language": "text", "rich_text": [{ "plain_text": "a\n\n\nb" }] } }),
+        json!({ "type": "pdf", "pdf": {} }),
+    ];
+    let markdown = convert_blocks_to_markdown(&blocks, &Config::default());
+    assert_eq!(markdown, "One\n\nTwo\n\n```text\na\n\n\nb\n```\n");
+    assert!(!markdown.replace("a\n\n\nb", "").contains("\n\n\n"));
+}
+
+#[test]
+fn rendered_equations_become_images() {
+    let blocks = vec![
+        json!({
+            "type": "equation",
+            "equation": { "expression": "a<b", "anki_filename": "notion2anki-equation-1.png" },
+        }),
+        json!({
+            "type": "paragraph",
+            "paragraph": { "rich_text": [
+                { "type": "text", "plain_text": "so " },
+                { "type": "equation", "equation": { "expression": "x^2" }, "plain_text": "x^2" },
+            ] },
+        }),
+    ];
+    assert_eq!(
+        convert_blocks_to_markdown(&blocks, &Config::default()),
+        "<img src=\"notion2anki-equation-1.png\" alt=\"a&lt;b\">\n\nso \\(x^2\\)\n"
+    );
+}
+
+#[test]
+fn blocks_without_a_conversion_are_counted_by_type() {
+    let blocks = vec![
+        json!({ "type": "audio", "audio": {} }),
+        json!({ "type": "table_of_contents", "table_of_contents": {} }),
+        json!({
+            "type": "toggle",
+            "toggle": { "rich_text": [{ "plain_text": "More" }] },
+            "children": [{ "type": "audio", "audio": {} }, { "type": "link_preview", "link_preview": {} }],
+        }),
+    ];
+    let skipped = skipped_block_types(&blocks, &Config::default());
+    assert_eq!(skipped.into_iter().collect::<Vec<_>>(), [("audio".to_string(), 2), ("link_preview".to_string(), 1)]);
+}
+
+#[test]
+fn subpages_imported_on_their_own_pass_the_strict_check() {
+    let blocks = vec![
+        json!({ "id": "verbs", "type": "child_page", "child_page": { "title": "Verbs" } }),
+        json!({ "type": "child_database", "child_database": { "title": "Words" } }),
+        json!({ "type": "link_to_page", "link_to_page": { "type": "page_id", "page_id": "nouns" } }),
+    ];
+    let strict = Config {
+        strict: true,
+        ..Config::default()
+    };
+    let summary = Summary { skipped_blocks: skipped_block_types(&blocks, &strict), ..Summary::default() };
+    assert_eq!(summary.skipped_blocks.len(), 3);
+    assert!(summary.fails_strict(&strict));
+    
+    let recursing = Config {
+        recurse_subpages: true,
+        ..strict
+    };
+    let summary = Summary { skipped_blocks: skipped_block_types(&blocks, &recursing), ..Summary::default() };
+    assert!(!summary.fails_strict(&recursing));
+}
+
+#[test]
+fn quotes_become_hints_of_toggle_cards() {
+    let blocks = vec![json!({
+        "type": "toggle",
+        "toggle": { "rich_text": [{ "plain_text": "Capital of France?" }] },
+        "children": [
+            { "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": "Paris" }] } },
+            { "type": "quote", "quote": { "rich_text": [{ "plain_text": "Think of the Seine" }] } },
+        ],
+    })];
+    let config = Config {
+        card_format: CardFormat::Toggle,
+        quote_as_hint: true,
+        ..Config::default()
+    };
+    let markdown = convert_blocks_to_markdown(&blocks, &config);
+    let cards = notion2anki::parse_flashcards_from_markdown(&markdown, &config);
+    
+    assert_eq!(cards.len(), 1);
+    assert_eq!(cards[0].answer, "Paris\n<details class=\"hint\"><summary>Hint</summary>Think of the Seine</details>");
+}
@@ -0,0 +1,173 @@
+use notion2anki::notion::{child_pages, fetch_page_content, normalize_page_id, page_ancestors, parse_block_url, title_matches};
+use notion2anki::{Config, NotionPage, TitleMatch};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Answer Notion requests on a local port, one connection per request: the
+/// JSON body `respond` gives for the request path, or a 404 for `None`.
+/// Returns the base URL to use as `notion_api_url`.
+async fn serve_notion(respond: fn(&str) -> Option<Value>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).into_owned();
+            let path = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+            let (status, body) = match respond(&path) {
+                Some(body) => ("200 OK", body),
+                None => ("404 Not Found", json!({ "object": "error", "status": 404, "message": "Could not find block" })),
+            };
+            let body = body.to_string();
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    url
+}
+
+#[test]
+fn page_ids_are_normalized_to_dashed_uuids() {
+    let dashed = "1f2e3d4c-5b6a-7980-a1b2-c3d4e5f60718";
+    assert_eq!(normalize_page_id(dashed).as_deref(), Some(dashed));
+    assert_eq!(normalize_page_id("1F2E3D4C5B6A7980A1B2C3D4E5F60718").as_deref(), Some(dashed));
+}
+
+#[test]
+fn malformed_page_ids_are_rejected() {
+    assert_eq!(normalize_page_id(""), None);
+    assert_eq!(normalize_page_id("1f2e3d4c5b6a7980a1b2c3d4e5f6071"), None);
+    assert_eq!(normalize_page_id("1f2e3d4c5b6a7980a1b2c3d4e5f6071z"), None);
+}
+
+#[test]
+fn page_titles_match_by_prefix_or_substring() {
+    assert!(title_matches("Flashcards: Biology", "Flashcards:", TitleMatch::Prefix));
+    assert!(!title_matches("Biology Flashcards:", "Flashcards:", TitleMatch::Prefix));
+    assert!(title_matches("Biology Flashcards:", "Flashcards:", TitleMatch::Contains));
+    assert!(!title_matches("Biology notes", "Flashcards:", TitleMatch::Contains));
+}
+
+#[test]
+fn page_titles_are_read_from_the_title_property() {
+    let page: NotionPage = serde_json::from_value(json!({
+        "id": "1f2e3d4c-5b6a-7980-a1b2-c3d4e5f60718",
+        "properties": {
+            "Tags": { "type": "multi_select", "multi_select": [] },
+            "Name": { "type": "title", "title": [
+                { "type": "text", "plain_text": "Flashcards: " },
+                { "type": "text", "plain_text": "Biology" },
+            ] },
+        },
+    }))
+    .unwrap();
+    assert_eq!(page.title.as_deref(), Some("Flashcards: Biology"));
+    
+    let untitled: NotionPage = serde_json::from_value(json!({
+        "id": "1f2e3d4c-5b6a-7980-a1b2-c3d4e5f60718",
+        "properties": { "title": { "type": "title", "title": [] } },
+    }))
+    .unwrap();
+    assert_eq!(untitled.title, None);
+}
+
+#[test]
+fn database_rows_know_their_database() {
+    let row: NotionPage = serde_json::from_value(json!({
+        "id": "row",
+        "parent": { "type": "database_id", "database_id": "cards" },
+        "properties": {},
+    }))
+    .unwrap();
+    assert_eq!(row.parent_database_id.as_deref(), Some("cards"));
+    assert_eq!(row.parent_page_id, None);
+}
+
+#[test]
+fn block_links_give_the_page_and_block_id() {
+    let link = "https://www.notion.so/team/Biology-Flashcards-1f2e3d4c5b6a7980a1b2c3d4e5f60718?pvs=4#0a1b2c3d4e5f60718293a4b5c6d7e8f9";
+    assert_eq!(
+        parse_block_url(link),
+        Some((
+            "1f2e3d4c-5b6a-7980-a1b2-c3d4e5f60718".to_string(),
+            "0a1b2c3d-4e5f-6071-8293-a4b5c6d7e8f9".to_string(),
+        ))
+    );
+    // A page link without a block, or with a broken block id, is no block link
+    assert_eq!(parse_block_url("https://www.notion.so/Biology-1f2e3d4c5b6a7980a1b2c3d4e5f60718"), None);
+    assert_eq!(parse_block_url("https://www.notion.so/Biology-1f2e3d4c5b6a7980a1b2c3d4e5f60718#0a1b2c"), None);
+}
+
+#[test]
+fn subpages_are_found_nested_in_other_blocks() {
+    let blocks = vec![
+        json!({ "id": "page-a", "type": "child_page", "child_page": { "title": "Verbs" } }),
+        json!({
+            "id": "toggle",
+            "type": "toggle",
+            "toggle": { "rich_text": [] },
+            "children": [{ "id": "1f2e3d4c-page-b", "type": "child_page", "child_page": { "title": " " } }],
+        }),
+    ];
+    assert_eq!(child_pages(&blocks), vec![
+        ("page-a".to_string(), "Verbs".to_string()),
+        ("1f2e3d4c-page-b".to_string(), "Page-1f2e3d4c".to_string()),
+    ]);
+}
+
+#[tokio::test]
+async fn ancestors_follow_the_parent_pages_root_first() {
+    let page = |id: &str, title: &str, parent: serde_json::Value| -> NotionPage {
+        serde_json::from_value(json!({
+            "id": id,
+            "parent": parent,
+            "properties": { "title": { "type": "title", "title": [{ "plain_text": title }] } },
+        }))
+        .unwrap()
+    };
+    let pages = vec![
+        page("cells", "Cells", json!({ "type": "page_id", "page_id": "biology" })),
+        page("biology", "Biology", json!({ "type": "page_id", "page_id": "root" })),
+        page("root", "Root", json!({ "type": "workspace", "workspace": true })),
+    ];
+    let ancestors = page_ancestors(&pages, &Config::default()).await;
+    assert_eq!(ancestors["cells"], vec!["Root".to_string(), "Biology".to_string()]);
+    assert_eq!(ancestors["root"], Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn synced_blocks_whose_original_isnt_shared_are_left_empty() {
+    let url = serve_notion(|path| {
+        path.starts_with("/blocks/page/children").then(|| {
+            json!({
+                "results": [
+                    {
+                        "id": "copy",
+                        "type": "synced_block",
+                        "has_children": true,
+                        "synced_block": { "synced_from": { "type": "block_id", "block_id": "original" } },
+                    },
+                    { "id": "text", "type": "paragraph", "has_children": false, "paragraph": { "rich_text": [] } },
+                ],
+                "has_more": false,
+            })
+        })
+    })
+    .await;
+    let config = Config {
+        notion_api_url: url,
+        ..Config::default()
+    };
+    
+    let blocks = fetch_page_content("page", &config).await.unwrap();
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0]["children"], json!([]));
+    assert_eq!(blocks[1]["id"], "text");
+}
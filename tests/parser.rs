@@ -0,0 +1,331 @@
+use notion2anki::markdown::convert_blocks_with_sources;
+use notion2anki::media::PageMedia;
+use notion2anki::parser::{append_comments, apply_card_templates, parse_flashcards_with_sources, remove_repeated_cards, Flashcard};
+use notion2anki::{blocks_to_flashcards, parse_flashcards_from_markdown, CardDedupe, CardFormat, Config, LongAnswer};
+use serde_json::json;
+use std::collections::HashSet;
+
+fn parse(markdown: &str) -> Vec<(String, String)> {
+    parse_with(markdown, Config::default())
+}
+
+fn parse_with(markdown: &str, config: Config) -> Vec<(String, String)> {
+    parse_flashcards_from_markdown(markdown, &config)
+        .into_iter()
+        .map(|card| (card.question, card.answer))
+        .collect()
+}
+
+#[test]
+fn multi_line_answer() {
+    let cards = parse("```\n问题: Steps?\n答案: first\nsecond\nthird\n```\n");
+    assert_eq!(cards, vec![("Steps?".to_string(), "first\nsecond\nthird".to_string())]);
+}
+
+#[test]
+fn answer_starting_on_next_line() {
+    let cards = parse("```\n问题: 水的化学式是什么？\n回答:\nH₂O\n```\n");
+    assert_eq!(cards, vec![("水的化学式是什么？".to_string(), "H₂O".to_string())]);
+}
+
+#[test]
+fn back_to_back_questions_without_blank_line() {
+    let cards = parse("```\nQuestion: A?\nAnswer: a\nQuestion: B?\nAnswer: b\n```\n");
+    assert_eq!(
+        cards,
+        vec![
+            ("A?".to_string(), "a".to_string()),
+            ("B?".to_string(), "b".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn question_with_empty_answer_is_dropped() {
+    let cards = parse("```\n问题: No answer\n答案:\n问题: Has answer\n答案: yes\n```\n");
+    assert_eq!(cards, vec![("Has answer".to_string(), "yes".to_string())]);
+}
+
+#[test]
+fn full_width_colons() {
+    let cards = parse("```\n问题：法国的首都？\n答案：巴黎\n```\n");
+    assert_eq!(cards, vec![("法国的首都？".to_string(), "巴黎".to_string())]);
+}
+
+#[test]
+fn content_outside_code_blocks_is_ignored() {
+    let cards = parse("问题: Outside\n答案: ignored\n\n```\n问题: Inside\n答案: kept\n```\n");
+    assert_eq!(cards, vec![("Inside".to_string(), "kept".to_string())]);
+}
+
+#[test]
+fn heading_format_uses_following_blocks_as_answer() {
+    let config = Config {
+        card_format: CardFormat::Heading,
+        ..Config::default()
+    };
+    let markdown = "Intro text\n\n# Chapter\n\n## What is Rust?\n\nA language\n\n- fast\n- safe\n## Empty\n\n";
+    let cards = parse_with(markdown, config);
+    assert_eq!(cards, vec![("What is Rust?".to_string(), "A language\n- fast\n- safe".to_string())]);
+}
+
+#[test]
+fn comments_in_code_blocks_are_not_headings() {
+    let config = Config {
+        card_format: CardFormat::Heading,
+        ..Config::default()
+    };
+    let markdown = "## How do I list files?\n\n```bash\n# long format\nls -l\n\n# hidden files too\nls -a\n```\n\n## Next\n\nText\n";
+    let cards = parse_with(markdown, config);
+    assert_eq!(cards.len(), 2);
+    assert_eq!(cards[0], (
+        "How do I list files?".to_string(),
+        "```bash\n# long format\nls -l\n\n# hidden files too\nls -a\n```".to_string()
+    ));
+}
+
+#[test]
+fn cloze_format_makes_one_card_per_cloze_line() {
+    let config = Config {
+        card_format: CardFormat::Cloze,
+        ..Config::default()
+    };
+    let markdown = "The capital of France is {{c1::Paris}}\n\nNo deletion here\n\n{{c1::H₂O}} is {{c2::water}}\n";
+    let cards = parse_flashcards_from_markdown(markdown, &config);
+    assert_eq!(cards.len(), 2);
+    assert!(cards.iter().all(|card| card.is_cloze()));
+    assert_eq!(cards[1].question, "{{c1::H₂O}} is {{c2::water}}");
+}
+
+#[test]
+fn separator_line_ends_the_card() {
+    let cards = parse("```\n问题: Parts?\n答案: one\n---\ntwo\n```\n");
+    assert_eq!(cards, vec![("Parts?".to_string(), "one".to_string())]);
+}
+
+#[test]
+fn custom_markers_accept_either_colon() {
+    let config = Config {
+        question_markers: vec!["Question".to_string()],
+        answer_markers: vec!["Réponse:".to_string()],
+        ..Config::default()
+    };
+    let cards = parse_with("```\nQuestion: Capitale ?\nRéponse： Paris\n```\n", config);
+    assert_eq!(cards, vec![("Capitale ?".to_string(), "Paris".to_string())]);
+}
+
+#[test]
+fn cards_with_a_blank_question_are_dropped() {
+    let cards = parse("```\n问题:\n答案: orphan answer\n问题: Kept?\n答案: yes\n```\n");
+    assert_eq!(cards, vec![("Kept?".to_string(), "yes".to_string())]);
+}
+
+#[test]
+fn cards_know_the_block_their_question_is_in() {
+    let code = |id: &str, text: &str| json!({
+        "id": id,
+        "type": "code",
+        "code": { "language": "plain text", "rich_text": [{ "plain_text": text }] },
+    });
+    let blocks = vec![
+        code("block-a", "Question: A?\nAnswer: a\nQuestion: B?\nAnswer: b"),
+        json!({ "id": "block-b", "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": "Notes" }] } }),
+        code("block-c", "Question: C?\nAnswer: c"),
+    ];
+    let config = Config::default();
+    let (markdown, block_starts) = convert_blocks_with_sources(&blocks, &config);
+    let flashcards = parse_flashcards_with_sources(&markdown, &block_starts, &config);
+    
+    let sources: Vec<_> = flashcards.iter().map(|card| card.source_block.as_deref()).collect();
+    assert_eq!(sources, vec![Some("block-a"), Some("block-a#1"), Some("block-c")]);
+}
+
+#[test]
+fn short_questions_are_not_mistaken_for_earlier_answers() {
+    let toggle = |id: &str, question: &str, answer: &str| json!({
+        "id": id,
+        "type": "toggle",
+        "toggle": { "rich_text": [{ "plain_text": question }] },
+        "children": [{ "id": format!("{}-answer", id), "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": answer }] } }],
+    });
+    let blocks = vec![toggle("block-a", "Is water wet?", "Yes"), toggle("block-b", "Yes", "The word for agreeing")];
+    let config = Config {
+        card_format: CardFormat::Toggle,
+        ..Config::default()
+    };
+    let (markdown, block_starts) = convert_blocks_with_sources(&blocks, &config);
+    let flashcards = parse_flashcards_with_sources(&markdown, &block_starts, &config);
+    
+    let sources: Vec<_> = flashcards.iter().map(|card| card.source_block.as_deref()).collect();
+    assert_eq!(sources, vec![Some("block-a"), Some("block-b")]);
+}
+
+#[test]
+fn templates_fill_the_front_and_back_of_basic_cards() {
+    let config = Config {
+        front_template: Some("[{title}] {front}".to_string()),
+        back_template: Some("{back} {other}".to_string()),
+        ..Config::default()
+    };
+    let mut flashcards = parse_flashcards_from_markdown("```\nQuestion: Use {back}?\nAnswer: yes\n```\n", &config);
+    apply_card_templates(&mut flashcards, "Tips & Tricks", &PageMedia::default(), &config);
+    
+    assert_eq!(flashcards[0].question, "[Tips &amp; Tricks] Use {back}?");
+    assert_eq!(flashcards[0].answer, "yes {other}");
+}
+
+#[test]
+fn blocks_become_flashcards_with_their_reversed_copies() {
+    let blocks = vec![json!({
+        "id": "block-a",
+        "type": "toggle",
+        "toggle": { "rich_text": [{ "plain_text": "Capital of France?" }] },
+        "children": [{ "type": "paragraph", "paragraph": { "rich_text": [{ "plain_text": "Paris" }] } }],
+    })];
+    let config = Config {
+        card_format: CardFormat::Toggle,
+        reverse_cards: true,
+        ..Config::default()
+    };
+    let cards: Vec<_> = blocks_to_flashcards(&blocks, &config)
+        .into_iter()
+        .map(|card| (card.question, card.answer, card.source_block.unwrap_or_default()))
+        .collect();
+    assert_eq!(cards, vec![
+        ("Capital of France?".to_string(), "Paris".to_string(), "block-a".to_string()),
+        ("Paris".to_string(), "Capital of France?".to_string(), "block-a:reversed".to_string()),
+    ]);
+}
+
+#[test]
+fn repeated_cards_of_a_deck_are_dropped() {
+    let card = |question: &str, answer: &str| Flashcard {
+        question: question.to_string(),
+        answer: answer.to_string(),
+        fields: Vec::new(),
+        audio: None,
+        source_block: None,
+        source_question: None,
+        tags: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut cards = vec![card("Q", "A"), card("Q", "A"), card("Q", "B")];
+    assert_eq!(remove_repeated_cards(&mut cards, "Deck", &mut seen, CardDedupe::FrontBack), 1);
+    assert_eq!(cards.len(), 2);
+    
+    // Later pages of the run are checked against the cards kept before
+    let mut other_page = vec![card("Q", "C"), card("Q", "A")];
+    assert_eq!(remove_repeated_cards(&mut other_page, "Deck", &mut seen, CardDedupe::FrontBack), 1);
+    let mut other_deck = vec![card("Q", "A")];
+    assert_eq!(remove_repeated_cards(&mut other_deck, "Other", &mut seen, CardDedupe::FrontBack), 0);
+    
+    let mut same_front = vec![card("Q", "A"), card("Q", "B")];
+    assert_eq!(remove_repeated_cards(&mut same_front, "Deck", &mut HashSet::new(), CardDedupe::Front), 1);
+    assert_eq!(same_front[0].answer, "A");
+}
+
+#[test]
+fn answers_over_the_limit_are_skipped_or_cut_off() {
+    let markdown = "```\nQuestion: A?\nAnswer: short\nQuestion: B?\nAnswer: much too long\n```\n";
+    let config = Config {
+        max_answer_chars: Some(8),
+        ..Config::default()
+    };
+    assert_eq!(parse_with(markdown, config.clone()), vec![("A?".to_string(), "short".to_string())]);
+    
+    let config = Config {
+        long_answer: LongAnswer::Truncate,
+        ..config
+    };
+    assert_eq!(parse_with(markdown, config)[1], ("B?".to_string(), "much too…".to_string()));
+}
+
+#[test]
+fn cut_off_html_answers_keep_their_tags_whole() {
+    let markdown = "```\nQuestion: A?\nAnswer: <b>bold &amp; <i>long</i></b> text\n```\n";
+    let config = Config {
+        max_answer_chars: Some(13),
+        long_answer: LongAnswer::Truncate,
+        ..Config::default()
+    };
+    assert_eq!(parse_with(markdown, config.clone())[0].1, "<b>bold &amp;…</b>");
+    
+    let config = Config {
+        max_answer_chars: Some(20),
+        ..config
+    };
+    assert_eq!(parse_with(markdown, config)[0].1, "<b>bold &amp; <i>lon…</i></b>");
+}
+
+#[test]
+fn templates_show_the_page_icon() {
+    let config = Config {
+        front_template: Some("{icon} {front}".to_string()),
+        ..Config::default()
+    };
+    let mut flashcards = parse_flashcards_from_markdown("```\nQuestion: Q?\nAnswer: A\n```\n", &config);
+    let media = PageMedia {
+        icon: "🧪".to_string(),
+        cover: String::new(),
+    };
+    apply_card_templates(&mut flashcards, "Chemistry", &media, &config);
+    
+    assert_eq!(flashcards[0].question, "🧪 Q?");
+}
+
+#[test]
+fn comments_are_added_below_the_answer() {
+    let mut card = Flashcard {
+        question: "Capital of France?".to_string(),
+        answer: "Paris".to_string(),
+        fields: Vec::new(),
+        audio: None,
+        source_block: Some("block".to_string()),
+        source_question: None,
+        tags: Vec::new(),
+    };
+    append_comments(&mut card, &["Since 508".to_string(), "Not Lyon".to_string()]);
+    assert_eq!(card.answer, "Paris\n\n<b>Comment:</b> Since 508\n<b>Comment:</b> Not Lyon");
+    
+    // Without an answer there's nothing to set them apart from
+    card.answer.clear();
+    append_comments(&mut card, &["Since 508".to_string()]);
+    assert_eq!(card.answer, "<b>Comment:</b> Since 508");
+    
+    append_comments(&mut card, &[]);
+    assert_eq!(card.answer, "<b>Comment:</b> Since 508");
+}
+
+#[test]
+fn inline_format_splits_lines_at_the_delimiter() {
+    let config = Config {
+        card_format: CardFormat::Inline,
+        ..Config::default()
+    };
+    let markdown = "# Spanish\n- hola :: hello\n1. la hora :: the time :: o'clock\nNo delimiter here\n\n- {{c1::Gracias}} means thanks\n";
+    let cards = parse_flashcards_from_markdown(markdown, &config);
+    assert_eq!(cards.len(), 3);
+    assert_eq!((cards[0].question.as_str(), cards[0].answer.as_str()), ("hola", "hello"));
+    // Only the first delimiter splits the line
+    assert_eq!((cards[1].question.as_str(), cards[1].answer.as_str()), ("la hora", "the time :: o'clock"));
+    assert!(cards[2].is_cloze());
+    
+    let config = Config {
+        inline_delimiter: " = ".to_string(),
+        ..config
+    };
+    assert_eq!(parse_with("perro = dog\n", config), vec![("perro".to_string(), "dog".to_string())]);
+}
+
+#[test]
+fn inline_to_do_items_lose_their_checkbox() {
+    let config = Config {
+        card_format: CardFormat::Inline,
+        ..Config::default()
+    };
+    let markdown = "- [ ] Capital of France :: Paris\n- [x] Capital of Spain :: Madrid\n";
+    assert_eq!(parse_with(markdown, config), vec![
+        ("Capital of France".to_string(), "Paris".to_string()),
+        ("Capital of Spain".to_string(), "Madrid".to_string()),
+    ]);
+}
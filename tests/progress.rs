@@ -0,0 +1,29 @@
+use notion2anki::progress::Progress;
+use notion2anki::Config;
+
+#[test]
+fn the_bar_fills_up_with_the_pages_done() {
+    let mut progress = Progress::new(4, &Config::default());
+    assert_eq!(progress.line(0), format!("[{}] 0/4 pages, 0 cards added", "-".repeat(30)));
+    
+    progress.next_page();
+    assert_eq!(
+        progress.line(3),
+        format!("[{}{}] 1/4 pages, 3 cards added", "#".repeat(7), "-".repeat(23))
+    );
+    for _ in 0..3 {
+        progress.next_page();
+    }
+    assert_eq!(progress.line(9), format!("[{}] 4/4 pages, 9 cards added", "#".repeat(30)));
+}
+
+#[test]
+fn the_bar_is_only_shown_in_a_terminal_without_logs() {
+    let config = Config::default();
+    assert!(!Progress::enabled(&config, false));
+    assert!(!Progress::enabled(&Config { debug_mode: true, ..config.clone() }, true));
+    assert!(!Progress::enabled(&Config { progress: false, ..config.clone() }, true));
+    // RUST_LOG turns on logs that would draw over the bar
+    assert!(!Progress::enabled(&Config { log_filter: Some("warn".to_string()), ..config.clone() }, true));
+    assert!(Progress::enabled(&config, true));
+}
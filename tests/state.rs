@@ -0,0 +1,55 @@
+use notion2anki::media::PageMedia;
+use notion2anki::parser::{apply_card_templates, Flashcard};
+use notion2anki::state::{card_key, since_timestamp, SyncState};
+use notion2anki::Config;
+
+#[test]
+fn pages_edited_before_the_last_sync_are_unchanged() {
+    let state = SyncState {
+        last_sync: Some("2024-03-01T12:30:40.000Z".to_string()),
+        ..SyncState::default()
+    };
+    assert!(!state.is_changed("2024-03-01T12:00:00.000Z"));
+    // Notion rounds to the minute, so 12:30 may be an edit at 12:30:50
+    assert!(state.is_changed("2024-03-01T12:30:00.000Z"));
+    assert!(state.is_changed("2024-03-02T08:00:00.000Z"));
+}
+
+#[test]
+fn everything_is_changed_without_a_previous_sync() {
+    assert!(SyncState::default().is_changed("2024-03-01T12:00:00.000Z"));
+}
+
+#[test]
+fn since_accepts_dates_and_times() {
+    assert_eq!(since_timestamp("2024-01-01").as_deref(), Some("2024-01-01T00:00:00.000Z"));
+    assert_eq!(since_timestamp("2024-01-01T09:00:00+01:00").as_deref(), Some("2024-01-01T08:00:00.000Z"));
+    assert_eq!(since_timestamp("January 2024"), None);
+}
+
+#[test]
+fn card_keys_ignore_the_front_template_and_page_title() {
+    let card = Flashcard {
+        question: "Capital of France?".to_string(),
+        answer: "Paris".to_string(),
+        fields: Vec::new(),
+        audio: None,
+        source_block: Some("block".to_string()),
+        source_question: None,
+        tags: Vec::new(),
+    };
+    let key = card_key("page", &card);
+    // Another card gets another key, so --prune can tell them apart
+    assert_ne!(card_key("page", &Flashcard { question: "Capital of Spain?".to_string(), ..card.clone() }), key);
+    
+    for (title, template) in [("Geography", "{title}: {front}"), ("Europe", "{title}: {front}"), ("Europe", "<b>{front}</b>")] {
+        let config = Config {
+            front_template: Some(template.to_string()),
+            ..Config::default()
+        };
+        let mut cards = vec![card.clone()];
+        apply_card_templates(&mut cards, title, &PageMedia::default(), &config);
+        assert_ne!(cards[0].question, card.question);
+        assert_eq!(card_key("page", &cards[0]), key);
+    }
+}